@@ -0,0 +1,171 @@
+use crate::error;
+use crate::utils::write_atomic;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use snafu::{OptionExt, ResultExt, whatever};
+use std::path::{Path, PathBuf};
+
+/// Directory name under `$XDG_CACHE_HOME` used when `--cache-dir` is omitted.
+const CACHE_DIR: &str = "flatpak-jetbrain-updater";
+
+/// Kept apart from the bodies, so that the body tree stays a plain mirror of the server.
+const VALIDATORS_DIR: &str = ".validators";
+
+/// What the server said about a cached body, sent back to ask whether it is still current.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Validators {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+impl Validators {
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.etag.is_none() && self.last_modified.is_none()
+    }
+}
+
+/// Response bodies on disk, laid out by URL so that they can be archived and read by people,
+/// e.g. `download.jetbrains.com/webstorm/WebStorm-2025.1.tar.gz.sha256`.
+pub struct Cache {
+    dir: PathBuf,
+}
+
+impl Cache {
+    #[inline]
+    pub fn new(dir: PathBuf) -> Cache {
+        Cache { dir }
+    }
+
+    /// `$XDG_CACHE_HOME/flatpak-jetbrain-updater`, or `~/.cache/flatpak-jetbrain-updater`.
+    pub fn default_dir() -> Option<PathBuf> {
+        let dir = std::env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))?;
+        Some(dir.join(CACHE_DIR))
+    }
+
+    #[inline]
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Where the body of `url` is stored, the query becomes part of the file name after `@`.
+    #[inline]
+    pub fn path_for(&self, url: &str) -> error::Result<PathBuf> {
+        Ok(self.dir.join(Self::relative_path(url)?))
+    }
+
+    #[inline]
+    fn validators_path(&self, url: &str) -> error::Result<PathBuf> {
+        Ok(self
+            .dir
+            .join(VALIDATORS_DIR)
+            .join(Self::relative_path(url)?))
+    }
+
+    fn relative_path(url: &str) -> error::Result<PathBuf> {
+        let parsed =
+            Url::parse(url).with_whatever_context(|e| format!("Invalid URL {}: {}", url, e))?;
+        let host = parsed
+            .host_str()
+            .with_whatever_context(|| format!("URL {} has no host", url))?;
+        let mut path = PathBuf::from(host);
+        // The parser has already resolved `.` and `..`, so the segments stay below `dir`
+        let segments = parsed
+            .path_segments()
+            .map(|segments| segments.filter(|s| !s.is_empty()).collect::<Vec<&str>>())
+            .unwrap_or_default();
+        let (file, parents) = match segments.split_last() {
+            Some((file, parents)) => (file.to_string(), parents),
+            None => ("index".to_string(), &[][..]),
+        };
+        path.extend(parents);
+        Ok(match parsed.query() {
+            Some(query) => path.join(format!("{}@{}", file, query.replace('/', "%2F"))),
+            None => path.join(file),
+        })
+    }
+
+    pub fn read(&self, url: &str) -> error::Result<Option<String>> {
+        Self::read_file(&self.path_for(url)?)
+    }
+
+    pub fn write(&self, url: &str, body: &str) -> error::Result<()> {
+        Self::write_file(&self.path_for(url)?, body)
+    }
+
+    /// The validators of the cached body of `url`, `None` unless both exist.
+    pub fn read_validators(&self, url: &str) -> error::Result<Option<Validators>> {
+        if !self.path_for(url)?.is_file() {
+            return Ok(None);
+        }
+        let path = self.validators_path(url)?;
+        Self::read_file(&path)?
+            .map(|content| {
+                serde_json::from_str(&content).with_whatever_context(|e| {
+                    format!("Failed to parse cache at {}, source: {}", path.display(), e)
+                })
+            })
+            .transpose()
+    }
+
+    pub fn write_validators(&self, url: &str, validators: &Validators) -> error::Result<()> {
+        let content = serde_json::to_string_pretty(validators)
+            .whatever_context("Failed to serialize validators, this is a bug")?;
+        Self::write_file(&self.validators_path(url)?, &content)
+    }
+
+    fn read_file(path: &Path) -> error::Result<Option<String>> {
+        match std::fs::read_to_string(path) {
+            Ok(body) => Ok(Some(body)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => whatever!("Failed to read cache at {}, source: {}", path.display(), e),
+        }
+    }
+
+    fn write_file(path: &Path, body: &str) -> error::Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).with_whatever_context(|e| {
+                format!(
+                    "Failed to create cache directory {}: {}",
+                    parent.display(),
+                    e
+                )
+            })?;
+        }
+        // Another run may read the cache at the same time, it must never see half a body
+        write_atomic(path, body)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_path_for() {
+        let cache = Cache::new(PathBuf::from("/cache"));
+        assert_eq!(
+            cache
+                .path_for("https://download.jetbrains.com/webstorm/WebStorm-2025.1.tar.gz.sha256")
+                .unwrap(),
+            PathBuf::from("/cache/download.jetbrains.com/webstorm/WebStorm-2025.1.tar.gz.sha256")
+        );
+        assert_eq!(
+            cache
+                .path_for(
+                    "https://data.services.jetbrains.com/products/releases?code=WS&type=release"
+                )
+                .unwrap(),
+            PathBuf::from(
+                "/cache/data.services.jetbrains.com/products/releases@code=WS&type=release"
+            )
+        );
+        assert_eq!(
+            cache.path_for("https://example.com/a/../../b").unwrap(),
+            PathBuf::from("/cache/example.com/b")
+        );
+    }
+}
@@ -0,0 +1,178 @@
+use crate::resolve::{Cooldown, Policy, ReleaseType};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use std::path::PathBuf;
+
+/// A tool to update Flatpak JetBrains' IDEs automatically.
+#[derive(Debug, Parser)]
+#[command(version, about)]
+pub struct Cli {
+    #[command(flatten)]
+    pub paths: PathArgs,
+
+    /// Release channel to follow, each channel also includes the more stable ones
+    #[arg(long, global = true, value_enum, default_value_t)]
+    pub channel: Channel,
+
+    /// Never touch the network, read the releases and checksums stored by `fetch` instead
+    #[arg(long, global = true)]
+    pub offline: bool,
+
+    /// Fail when the release lacks the download of an arch in the manifest,
+    /// instead of keeping that source as it is with a warning
+    #[arg(long, global = true)]
+    pub strict: bool,
+
+    /// Keep a copy of every file before it is replaced, as `<name>.<timestamp>.bak` next to it
+    #[arg(long, global = true)]
+    pub backup: bool,
+
+    #[command(flatten)]
+    pub policy: PolicyArgs,
+
+    /// Defaults to `update` when omitted
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Args)]
+pub struct PathArgs {
+    /// Directory of the Flathub checkout, defaults to the manifest's directory or `.`
+    #[arg(long, global = true)]
+    pub dir: Option<PathBuf>,
+
+    /// Path of the Flatpak manifest, detected inside `--dir` when omitted
+    #[arg(long, global = true)]
+    pub manifest: Option<PathBuf>,
+
+    /// Path of the appdata XML, detected inside `--dir` when omitted
+    #[arg(long, global = true)]
+    pub appdata: Option<PathBuf>,
+
+    /// TOML or YAML config merged over the built-in product registry,
+    /// defaults to `$XDG_CONFIG_HOME/flatpak-jetbrain-updater/config.toml` if present
+    #[arg(long, global = true)]
+    pub config: Option<PathBuf>,
+
+    /// Where API responses and checksums are stored, a vendored copy can be used with `--offline`,
+    /// defaults to `$XDG_CACHE_HOME/flatpak-jetbrain-updater`
+    #[arg(long, global = true)]
+    pub cache_dir: Option<PathBuf>,
+}
+
+/// Overrides the `[policy.<short>]` of the config for this run.
+#[derive(Debug, Args)]
+pub struct PolicyArgs {
+    /// Stay on exactly this version or build number
+    #[arg(long, global = true, value_name = "VERSION", group = "update_policy")]
+    pub pin: Option<String>,
+
+    /// Follow the newest release of one `majorVersion` line, e.g. 2024.3
+    #[arg(long, global = true, value_name = "MAJOR", group = "update_policy")]
+    pub track: Option<String>,
+
+    /// Follow the newest release, ignoring the policy in the config
+    #[arg(long, global = true, group = "update_policy")]
+    pub latest: bool,
+
+    /// Only adopt a release once it is at least this many days old, 0 adopts it right away
+    #[arg(long, global = true, value_name = "DAYS")]
+    pub min_age: Option<u32>,
+
+    /// Still list releases held back by `--min-age` in the appdata
+    #[arg(long, global = true)]
+    pub announce_held: bool,
+}
+
+impl PolicyArgs {
+    /// `None` if nothing was given on the command line.
+    pub fn to_policy(&self) -> Option<Policy> {
+        match (&self.pin, &self.track, self.latest) {
+            (Some(version), _, _) => Some(Policy::Pin(version.clone())),
+            (_, Some(major), _) => Some(Policy::Track(major.clone())),
+            (_, _, true) => Some(Policy::Latest),
+            _ => None,
+        }
+    }
+
+    /// Applies the command line over the cooldown of the config.
+    pub fn to_cooldown(&self, config: Cooldown) -> Cooldown {
+        Cooldown {
+            min_age: self.min_age.unwrap_or(config.min_age),
+            announce_held: self.announce_held || config.announce_held,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Subcommand)]
+pub enum Command {
+    /// Report whether an update exists, exits with 1 if so and 0 if up to date
+    Check,
+    /// Write the latest release into the manifest and appdata
+    Update {
+        /// Print a unified diff per file instead of writing anything
+        #[arg(long)]
+        dry_run: bool,
+        /// Download the Linux tarballs and check their size and checksum before writing
+        #[arg(long)]
+        verify: bool,
+        /// Commit the changed files on a new branch, the checkout must have no other changes
+        #[arg(long)]
+        git: bool,
+        /// Push the new branch to the remote of the `[git]` config, `origin` by default
+        #[arg(long, requires = "git")]
+        push: bool,
+    },
+    /// Show what `update` would change without writing anything, same as `update --dry-run`
+    Diff,
+    /// Store the releases and the checksums of the latest release in the cache for `--offline`
+    Fetch,
+    /// Write an older release into the manifest and drop the newer ones from the appdata,
+    /// the policy and `--min-age` do not apply
+    Rollback {
+        /// Version or build number to go back to, defaults to the release before the current one
+        version: Option<String>,
+        /// Print a unified diff per file instead of writing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Print the Markdown body of the pull request for the update, with the downloads and
+    /// the releases it skips
+    PrBody {
+        /// Version or build number the update starts from, defaults to the one in the manifest,
+        /// needed once `update` has already written the manifest
+        #[arg(long, value_name = "VERSION")]
+        from: Option<String>,
+        /// Write the body to this file instead of stdout
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+    /// Put the manifest and appdata of the last `--backup` back and delete that backup
+    Restore {
+        /// Print a unified diff per file instead of writing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Channel {
+    /// Stable releases only
+    #[default]
+    Release,
+    /// Release candidates and stable releases
+    Rc,
+    /// Early access previews, release candidates and stable releases
+    Eap,
+}
+
+impl Channel {
+    /// The release types requested from the JetBrains API for this channel.
+    #[inline]
+    pub fn release_types(self) -> &'static [ReleaseType] {
+        match self {
+            Channel::Release => &[ReleaseType::Release],
+            Channel::Rc => &[ReleaseType::Release, ReleaseType::Rc],
+            Channel::Eap => &[ReleaseType::Release, ReleaseType::Rc, ReleaseType::Eap],
+        }
+    }
+}
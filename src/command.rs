@@ -0,0 +1,1122 @@
+use crate::cache::Cache;
+use crate::cli::{Channel, Cli, Command, PathArgs};
+use crate::config::{Config, GitConfig};
+use crate::date::today;
+use crate::error;
+use crate::git::{Git, render_template};
+use crate::http::Http;
+use crate::resolve::{
+    Arch, Cooldown, Policy, PolicyDef, ProductInfo, ProductRelease, compare_release, release_age,
+};
+use crate::utils::{
+    Backup, FileChange, current_links, fetch_json, read_json, release_versions, render_pr_body,
+    render_xml, render_yaml, verify_appdata, verify_manifest, verify_release, write_atomic,
+};
+use snafu::{OptionExt, ResultExt, whatever};
+use std::cmp::Ordering;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+/// Exit code of `check` when a newer release is available.
+const UPDATE_AVAILABLE: u8 = 1;
+
+pub struct Context {
+    http: Http,
+    product_info: ProductInfo,
+    manifest: PathBuf,
+    appdata: PathBuf,
+    channel: Channel,
+    policy: Policy,
+    cooldown: Cooldown,
+    strict: bool,
+    backup: bool,
+    git: GitConfig,
+}
+
+impl Context {
+    pub fn from_cli(cli: &Cli) -> error::Result<Context> {
+        let args = &cli.paths;
+        let dir = checkout_dir(args);
+        let config = Config::load(args.config.as_deref())?;
+        let product_info = ProductInfo::resolve(&dir, args.manifest.as_deref(), &config.products)?;
+        let manifest = match &args.manifest {
+            Some(manifest) => manifest.clone(),
+            None => product_info
+                .find_manifest_from_path()
+                .whatever_context("Failed to find manifest path")?,
+        };
+        let appdata = args
+            .appdata
+            .clone()
+            .unwrap_or_else(|| product_info.appdata_path());
+        let cache = args
+            .cache_dir
+            .clone()
+            .or_else(Cache::default_dir)
+            .map(Cache::new);
+        let policy_def = config.policy.get(product_info.short());
+        let policy = match cli.policy.to_policy() {
+            Some(policy) => policy,
+            None => policy_def
+                .map(PolicyDef::to_policy)
+                .transpose()?
+                .unwrap_or_default(),
+        };
+        let cooldown = cli
+            .policy
+            .to_cooldown(policy_def.map(PolicyDef::to_cooldown).unwrap_or_default());
+        Ok(Context {
+            http: Http::new(&config.http, cache, cli.offline)?,
+            product_info,
+            manifest,
+            appdata,
+            channel: cli.channel,
+            policy,
+            cooldown,
+            strict: cli.strict,
+            backup: cli.backup,
+            git: config.git.clone(),
+        })
+    }
+}
+
+/// The directory of the Flathub checkout, `--dir` or else the directory of `--manifest`.
+fn checkout_dir(args: &PathArgs) -> PathBuf {
+    match (&args.dir, &args.manifest) {
+        (Some(dir), _) => dir.clone(),
+        (None, Some(manifest)) => manifest
+            .parent()
+            .filter(|p| !p.as_os_str().is_empty())
+            .map_or_else(|| PathBuf::from("."), Path::to_path_buf),
+        (None, None) => PathBuf::from("."),
+    }
+}
+
+/// Runs `command`, `restore` goes first as it must work while the manifest does not parse.
+pub async fn execute(cli: &Cli, command: Command) -> error::Result<ExitCode> {
+    if let Command::Restore { dry_run } = command {
+        return restore(&cli.paths, cli.backup, dry_run);
+    }
+    run(Context::from_cli(cli)?, command).await
+}
+
+async fn run(context: Context, command: Command) -> error::Result<ExitCode> {
+    let array = fetch_json(&context.http, &context.product_info, context.channel).await?;
+    let mut collection = read_json(&array)?;
+    if let Command::Rollback { version, dry_run } = &command {
+        return rollback(&context, collection, version.as_deref(), *dry_run).await;
+    }
+    // Newer releases than the chosen one are neither written to the manifest nor to the appdata
+    let newest = context
+        .policy
+        .select(&collection)
+        .with_whatever_context(|| {
+            format!(
+                "No {} release matches the policy {}",
+                context.product_info.display_name(),
+                context.policy
+            )
+        })?;
+    let selected = context
+        .policy
+        .select_aged(&collection, context.cooldown.min_age, today())
+        .with_whatever_context(|| {
+            format!(
+                "Every {} release matching the policy {} is younger than {} days",
+                context.product_info.display_name(),
+                context.policy,
+                context.cooldown.min_age
+            )
+        })?;
+
+    match command {
+        Command::Check => {
+            let held = (newest < selected).then(|| &collection[newest]);
+            check(&context, &collection[selected..], held)
+        }
+        Command::Update {
+            dry_run,
+            verify,
+            git,
+            push,
+        } => {
+            let target = drain_unlisted(&context, &mut collection, newest, selected);
+            let options = UpdateOptions {
+                dry_run,
+                verify,
+                git,
+                push,
+            };
+            update(&context, &mut collection, target, &context.policy, options).await
+        }
+        Command::Diff => {
+            let target = drain_unlisted(&context, &mut collection, newest, selected);
+            let options = UpdateOptions {
+                dry_run: true,
+                ..UpdateOptions::default()
+            };
+            update(&context, &mut collection, target, &context.policy, options).await
+        }
+        Command::Fetch => {
+            collection.drain(..selected);
+            fetch(&context, &mut collection).await
+        }
+        Command::PrBody { from, output } => {
+            pr_body(&context, &collection, selected, from, output.as_deref()).await
+        }
+        Command::Rollback { .. } | Command::Restore { .. } => {
+            unreachable!("{:?} returns before the policy applies", command)
+        }
+    }
+}
+
+/// Drops the releases which go into neither file and returns the new index of the selected one,
+/// the held back releases are kept for the appdata only if they are announced.
+fn drain_unlisted(
+    context: &Context,
+    collection: &mut Vec<ProductRelease>,
+    newest: usize,
+    selected: usize,
+) -> usize {
+    let first = if context.cooldown.announce_held {
+        newest
+    } else {
+        selected
+    };
+    collection.drain(..first);
+    selected - first
+}
+
+#[inline]
+fn latest<'a, 'b>(collection: &'a [ProductRelease<'b>]) -> error::Result<&'a ProductRelease<'b>> {
+    collection
+        .first()
+        .whatever_context("Failed to find any release in JSON")
+}
+
+/// The version or build in the first download link of the manifest.
+#[inline]
+fn manifest_version(context: &Context, links: &[(Arch, String)]) -> Option<String> {
+    links
+        .first()
+        .and_then(|(_, link)| context.product_info.version_from_link(link))
+}
+
+/// Whether every source of the manifest already points at `release`, an arch which `release`
+/// lacks stays as it is and counts as current.
+#[inline]
+fn is_current(links: &[(Arch, String)], release: &ProductRelease) -> bool {
+    links.iter().all(|(arch, link)| {
+        release
+            .platform(*arch)
+            .is_none_or(|platform| platform.link == link)
+    })
+}
+
+fn check(
+    context: &Context,
+    collection: &[ProductRelease],
+    held: Option<&ProductRelease>,
+) -> error::Result<ExitCode> {
+    let latest = latest(collection)?;
+    if context.policy != Policy::Latest {
+        println!("Following the update policy: {}", context.policy);
+    }
+    if let Some(held) = held {
+        let remaining = release_age(held, today())
+            .map(|age| {
+                format!(
+                    ", {} more day(s) to go",
+                    context.cooldown.min_age as i64 - age
+                )
+            })
+            .unwrap_or_default();
+        println!(
+            "{} {} ({}) is held back until it is {} days old{}",
+            context.product_info.display_name(),
+            held.version,
+            held.date,
+            context.cooldown.min_age,
+            remaining
+        );
+    }
+    let links = current_links(&context.product_info, &context.manifest)?;
+    let current = manifest_version(context, &links);
+    if is_current(&links, latest) || !context.policy.accepts(current.as_deref(), latest) {
+        println!(
+            "{} is up to date ({}), manifest is at {}",
+            context.product_info.display_name(),
+            latest.version,
+            current.as_deref().unwrap_or("unknown")
+        );
+        Ok(ExitCode::SUCCESS)
+    } else {
+        let current = current.unwrap_or_else(|| "unknown".to_string());
+        println!(
+            "{} {} ({}, {}{}) is available, manifest is at {}",
+            context.product_info.display_name(),
+            latest.version,
+            latest.release_type.as_api(),
+            latest.date,
+            latest
+                .build
+                .map(|build| format!(", build {}", build))
+                .unwrap_or_default(),
+            current
+        );
+        if let Some(notes_link) = latest.notes_link {
+            println!("Release notes: {}", notes_link);
+        }
+        Ok(ExitCode::from(UPDATE_AVAILABLE))
+    }
+}
+
+/// The flags of `update`, `diff` and `rollback` only set `dry_run`.
+#[derive(Debug, Clone, Copy, Default)]
+struct UpdateOptions {
+    dry_run: bool,
+    verify: bool,
+    git: bool,
+    push: bool,
+}
+
+async fn update(
+    context: &Context,
+    collection: &mut Vec<ProductRelease<'_>>,
+    target: usize,
+    policy: &Policy,
+    options: UpdateOptions,
+) -> error::Result<ExitCode> {
+    // Checked before anything is written, other changes would end up next to the update
+    let git = if options.git && !options.dry_run {
+        let git = Git::open(context.manifest.parent().unwrap_or(Path::new(".")))?;
+        git.ensure_clean()?;
+        Some(git)
+    } else {
+        None
+    };
+    let name = context.product_info.display_name();
+    let links = current_links(&context.product_info, &context.manifest)?;
+    let current = manifest_version(context, &links);
+    let mut manifest_release = collection
+        .get(target)
+        .filter(|release| !is_current(&links, release))
+        .filter(|release| policy.accepts(current.as_deref(), release))
+        .cloned();
+    if manifest_release.is_none()
+        && let Some(release) = collection.get(target)
+    {
+        if is_current(&links, release) {
+            println!("{} is up to date ({})", name, release.version);
+        } else {
+            println!(
+                "{} stays at {} in the manifest, {} is no update under the policy {}",
+                name,
+                current.as_deref().unwrap_or("unknown"),
+                release.version,
+                policy
+            );
+        }
+    }
+    // Held back releases may be announced before the manifest moves, so the appdata is judged
+    // by its own newest release
+    let listed = release_versions(&context.appdata)?.into_iter().next();
+    let appdata_moves = manifest_release.is_some()
+        || collection
+            .first()
+            .is_some_and(|release| policy.accepts(listed.as_deref(), release));
+    // `render_xml` drops the releases it already knows, so the newest one is taken beforehand
+    let newest_listed = collection.first().map(|release| release.version);
+    // Only a manifest update is committed, an announced release would take the branch of the
+    // update which follows it. A taken branch or a missing identity would only fail after both
+    // files are written
+    let commit = match (git, &manifest_release) {
+        (Some(git), Some(release)) => {
+            let values = commit_values(context, release, current.as_deref());
+            Some(UpdateCommit::prepare(context, git, &values)?)
+        }
+        (Some(_), None) if appdata_moves => {
+            println!(
+                "Only the appdata changes, --git commits updates of the manifest, nothing is committed"
+            );
+            None
+        }
+        _ => None,
+    };
+
+    let changes = [
+        if appdata_moves {
+            render_xml(&context.appdata, collection)?
+        } else {
+            FileChange::keep(&context.appdata)?
+        },
+        render_yaml(
+            &context.http,
+            &context.product_info,
+            &context.manifest,
+            manifest_release.as_mut(),
+            context.strict,
+        )
+        .await?,
+    ];
+    // The checksums are only resolved by `render_yaml`, nothing has been written so far
+    if options.verify
+        && let Some(release) = &manifest_release
+    {
+        let arches = links.iter().map(|(arch, _)| *arch).collect::<Vec<Arch>>();
+        verify_release(&context.http, release, &arches).await?;
+    }
+
+    if options.dry_run {
+        print_diff(&changes);
+    } else {
+        // Both files move together, the appdata must not claim a release the manifest lacks
+        FileChange::write_all(&changes, context.backup)?;
+        if let Err(e) = verify_written(context, &changes, manifest_release.as_ref(), newest_listed)
+        {
+            FileChange::revert_all(&changes)?;
+            whatever!("{}, both files have been reverted", e);
+        }
+        if let Some(commit) = commit
+            && changes.iter().any(FileChange::is_changed)
+        {
+            commit.commit(&changes, context, options.push)?;
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// The placeholders of the `[git]` templates.
+fn commit_values<'a>(
+    context: &'a Context,
+    release: &ProductRelease<'a>,
+    old: Option<&'a str>,
+) -> [(&'static str, &'a str); 5] {
+    [
+        ("product", context.product_info.display_name()),
+        ("old", old.unwrap_or("unknown")),
+        ("new", release.version),
+        ("build", release.build.unwrap_or("unknown")),
+        ("notes", release.notes_link.unwrap_or("unavailable")),
+    ]
+}
+
+/// The commit of an update on a new branch, named and described by the `[git]` templates.
+struct UpdateCommit {
+    git: Git,
+    branch: String,
+    message: String,
+}
+
+impl UpdateCommit {
+    /// Renders the templates and checks that the commit can be made, before anything is written.
+    fn prepare(
+        context: &Context,
+        git: Git,
+        values: &[(&str, &str)],
+    ) -> error::Result<UpdateCommit> {
+        let branch = render_template(&context.git.branch, values);
+        let message = render_template(&context.git.message, values);
+        git.ensure_new_branch(&branch)?;
+        git.ensure_identity()?;
+        Ok(UpdateCommit {
+            git,
+            branch,
+            message,
+        })
+    }
+
+    /// Commits the changed files, if that fails they are reverted and the branch is dropped.
+    fn commit(&self, changes: &[FileChange], context: &Context, push: bool) -> error::Result<()> {
+        let paths = changes
+            .iter()
+            .filter(|change| change.is_changed())
+            .map(FileChange::path)
+            .collect::<Vec<&Path>>();
+
+        if let Err(e) = self.git.create_branch(&self.branch) {
+            FileChange::revert_all(changes)?;
+            whatever!("{}, both files have been reverted", e);
+        }
+        if let Err(e) = self.git.commit(&paths, &self.message) {
+            FileChange::revert_all(changes)?;
+            self.git.discard_branch(&self.branch)?;
+            whatever!("{}, both files have been reverted", e);
+        }
+        if push {
+            self.git.push(&context.git.remote, &self.branch)?;
+        }
+        println!(
+            "Committed \"{}\" on the branch {}",
+            self.message.lines().next().unwrap_or_default(),
+            self.branch
+        );
+        Ok(())
+    }
+}
+
+/// Reads the written files back, the manifest must point at `release` and the appdata must
+/// list `newest_listed` first.
+fn verify_written(
+    context: &Context,
+    changes: &[FileChange; 2],
+    release: Option<&ProductRelease>,
+    newest_listed: Option<&str>,
+) -> error::Result<()> {
+    let [appdata, manifest] = changes;
+    if manifest.is_changed()
+        && let Some(release) = release
+    {
+        verify_manifest(&context.product_info, &context.manifest, release)?;
+    }
+    if appdata.is_changed()
+        && let Some(version) = newest_listed
+    {
+        verify_appdata(&context.appdata, version)?;
+    }
+    Ok(())
+}
+
+async fn rollback(
+    context: &Context,
+    mut collection: Vec<ProductRelease<'_>>,
+    version: Option<&str>,
+    dry_run: bool,
+) -> error::Result<ExitCode> {
+    let name = context.product_info.display_name();
+    let links = current_links(&context.product_info, &context.manifest)?;
+    let current = manifest_version(context, &links);
+    let target = match version {
+        Some(version) => Policy::Pin(version.to_string())
+            .select(&collection)
+            .with_whatever_context(|| {
+                format!("No {} release has the version or build {}", name, version)
+            })?,
+        None => current
+            .as_deref()
+            .and_then(|current| {
+                collection
+                    .iter()
+                    .position(|release| compare_release(release, current) == Some(Ordering::Less))
+            })
+            .with_whatever_context(|| {
+                format!(
+                    "Failed to find the {} release before the one in the manifest, please give a version",
+                    name
+                )
+            })?,
+    };
+    if let Some(current) = &current
+        && compare_release(&collection[target], current) == Some(Ordering::Greater)
+    {
+        whatever!(
+            "{} {} is newer than {} in the manifest, use `update --pin {}` to move forward",
+            name,
+            collection[target].version,
+            current,
+            collection[target].version
+        );
+    }
+
+    // The appdata keeps only the releases left in the collection, so the newer ones are dropped
+    collection.drain(..target);
+    let version = collection[0].version;
+    let policy = Policy::Pin(version.to_string());
+    let options = UpdateOptions {
+        dry_run,
+        ..UpdateOptions::default()
+    };
+    update(context, &mut collection, 0, &policy, options).await?;
+    if !dry_run {
+        println!(
+            "Rolled back {} to {}, pin it with `--pin {}` or in the config so that `update` stays there",
+            name, version, version
+        );
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// The manifest and appdata paths from `--manifest`, `--appdata` and the file names in the
+/// checkout, the product is not resolved as that would parse the manifest.
+fn restore_paths(args: &PathArgs) -> error::Result<(PathBuf, PathBuf)> {
+    let dir = checkout_dir(args);
+    let manifest = match &args.manifest {
+        Some(manifest) => manifest.clone(),
+        None => {
+            let entries = std::fs::read_dir(&dir).with_whatever_context(|e| {
+                format!("Failed to read directory {}: {}", dir.display(), e)
+            })?;
+            let found = entries
+                .filter_map(Result::ok)
+                .filter(|entry| entry.file_name().to_str().and_then(product_name).is_some())
+                .map(|entry| entry.path())
+                .collect::<Vec<PathBuf>>();
+            match found.as_slice() {
+                [manifest] => manifest.clone(),
+                [] => whatever!(
+                    "Failed to find a com.jetbrains.<Name> manifest in {}, please give --manifest",
+                    dir.display()
+                ),
+                _ => whatever!(
+                    "Found several manifests in {}, please choose one with --manifest",
+                    dir.display()
+                ),
+            }
+        }
+    };
+    let appdata = match &args.appdata {
+        Some(appdata) => appdata.clone(),
+        None => manifest
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(product_name)
+            .map(|name| dir.join(format!("com.jetbrains.{}.appdata.xml", name)))
+            .with_whatever_context(|| {
+                format!(
+                    "Failed to derive the appdata from {}, please give --appdata",
+                    manifest.display()
+                )
+            })?,
+    };
+    Ok((manifest, appdata))
+}
+
+/// The `<Name>` of a `com.jetbrains.<Name>.<yaml|yml|json>` manifest file name.
+#[inline]
+fn product_name(file_name: &str) -> Option<&str> {
+    let (name, ext) = file_name.strip_prefix("com.jetbrains.")?.rsplit_once('.')?;
+    (matches!(ext, "yaml" | "yml" | "json") && !name.is_empty() && !name.contains('.'))
+        .then_some(name)
+}
+
+fn restore(args: &PathArgs, backup: bool, dry_run: bool) -> error::Result<ExitCode> {
+    let (manifest, appdata) = restore_paths(args)?;
+    let paths = [&manifest, &appdata];
+    let backups = paths
+        .iter()
+        .map(|path| Backup::latest(path))
+        .collect::<error::Result<Vec<Option<Backup>>>>()?;
+    // Both files are backed up with the same timestamp, an older one belongs to another run
+    let stamp = backups
+        .iter()
+        .flatten()
+        .map(|backup| backup.stamp.clone())
+        .max()
+        .with_whatever_context(|| {
+            format!(
+                "Failed to find a backup of {} or {}",
+                manifest.display(),
+                appdata.display()
+            )
+        })?;
+    let restored = paths
+        .into_iter()
+        .zip(backups.iter())
+        .filter_map(|(path, backup)| {
+            backup
+                .as_ref()
+                .filter(|b| b.stamp == stamp)
+                .map(|b| (path, b))
+        })
+        .collect::<Vec<(&PathBuf, &Backup)>>();
+    let changes = restored
+        .iter()
+        .map(|(path, backup)| FileChange::from_backup(path, backup))
+        .collect::<error::Result<Vec<FileChange>>>()?;
+
+    if dry_run {
+        print_diff(&changes);
+        return Ok(ExitCode::SUCCESS);
+    }
+    FileChange::write_all(&changes, backup)?;
+    for (path, backup) in restored {
+        std::fs::remove_file(&backup.path).with_whatever_context(|e| {
+            format!(
+                r#"Restored "{}", but failed to delete the backup "{}", source: {:?}"#,
+                path.display(),
+                backup.path.display(),
+                e
+            )
+        })?;
+        println!("Restored {} from the backup of {}", path.display(), stamp);
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+async fn fetch(
+    context: &Context,
+    collection: &mut [ProductRelease<'_>],
+) -> error::Result<ExitCode> {
+    if context.http.is_offline() {
+        whatever!("`fetch` fills the cache for --offline, it can not run offline itself");
+    }
+    let cache = context
+        .http
+        .cache()
+        .whatever_context("Failed to find a cache directory, please set --cache-dir")?;
+    let latest = collection
+        .first_mut()
+        .whatever_context("Failed to find any release in JSON")?;
+    // Requesting the checksums is what stores them
+    latest.complete_checksum(&context.http).await?;
+    println!(
+        "Cached the releases of {} and the checksums of {} in {}",
+        context.product_info.display_name(),
+        latest.version,
+        cache.dir().display()
+    );
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Renders the pull request body for moving from `from`, or the manifest, to the selected
+/// release, every release of the channel in between is listed as skipped.
+async fn pr_body(
+    context: &Context,
+    collection: &[ProductRelease<'_>],
+    selected: usize,
+    from: Option<String>,
+    output: Option<&Path>,
+) -> error::Result<ExitCode> {
+    let name = context.product_info.display_name();
+    let links = current_links(&context.product_info, &context.manifest)?;
+    let old = from.or_else(|| manifest_version(context, &links));
+    let mut release = collection[selected].clone();
+    if let Some(old) = &old
+        && compare_release(&release, old).is_some_and(|ordering| ordering != Ordering::Greater)
+    {
+        whatever!(
+            "{} {} is no update from {}, pass the version before the update with --from",
+            name,
+            release.version,
+            old
+        );
+    }
+    release.complete_checksum(&context.http).await?;
+
+    let skipped = old
+        .as_deref()
+        .map(|old| {
+            collection[selected + 1..]
+                .iter()
+                .filter(|skipped| compare_release(skipped, old) == Some(Ordering::Greater))
+                .filter(|skipped| compare_release(skipped, release.version) == Some(Ordering::Less))
+                .cloned()
+                .collect::<Vec<ProductRelease>>()
+        })
+        .unwrap_or_default();
+    let arches = links.iter().map(|(arch, _)| *arch).collect::<Vec<Arch>>();
+    let body = render_pr_body(name, old.as_deref(), &release, &arches, &skipped);
+    match output {
+        Some(output) => write_atomic(output, &body)?,
+        None => print!("{}", body),
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn print_diff(changes: &[FileChange]) {
+    let mut changed = false;
+    for change in changes.iter().filter(|change| change.is_changed()) {
+        changed = true;
+        print!("{}", change.unified_diff());
+    }
+    if !changed {
+        println!("Nothing to change");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resolve::ReleasesResponse;
+    use clap::Parser;
+    use std::ffi::OsStr;
+
+    const RELEASES: &str = include_str!("../tests/fixtures/releases_WS.json");
+    const UPDATED: &str = include_str!("../tests/fixtures/com.jetbrains.WebStorm.yaml");
+    /// The appdata history matches the fixture, so an update only adds a release to it.
+    const APPDATA: &str = include_str!("../tests/fixtures/com.jetbrains.WebStorm.appdata_WS.xml");
+
+    fn updated_appdata() -> String {
+        APPDATA.replacen(
+            "  <releases>\n",
+            "  <releases>\n    <release version=\"2025.1\" date=\"2025-04-16\"/>\n",
+            1,
+        )
+    }
+
+    fn read_appdata(dir: &Path) -> String {
+        std::fs::read_to_string(dir.join("com.jetbrains.WebStorm.appdata.xml")).unwrap()
+    }
+
+    /// A checkout of the fixture manifest and appdata and a cache holding the releases, for
+    /// `--offline` runs.
+    fn offline_checkout(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("fju-{}-{}", name, std::process::id()));
+        let dir = root.join("repo");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::copy(
+            "tests/fixtures/com.jetbrains.WebStorm_bak.yaml",
+            dir.join("com.jetbrains.WebStorm.yaml"),
+        )
+        .unwrap();
+        std::fs::copy(
+            "tests/fixtures/com.jetbrains.WebStorm.appdata_WS.xml",
+            dir.join("com.jetbrains.WebStorm.appdata.xml"),
+        )
+        .unwrap();
+
+        let cache = Cache::new(root.join("cache"));
+        let api = Http::api_url("/products/releases?code=WS&type=release");
+        cache.write(&api, RELEASES).unwrap();
+        for (file, sha256) in [
+            (
+                "WebStorm-2025.1.tar.gz",
+                "f181eb1348c04a60f860fd9ea9616261c6c012a7916e76380652d129e87503ea",
+            ),
+            (
+                "WebStorm-2025.1-aarch64.tar.gz",
+                "51fd7420fb49ff8851d3b4b1079f8d7afebe9631a76e7d1d4fd3e66d42332c6c",
+            ),
+        ] {
+            let url = format!("https://download.jetbrains.com/webstorm/{}.sha256", file);
+            cache
+                .write(&url, &format!("{} *{}\n", sha256, file))
+                .unwrap();
+        }
+        root
+    }
+
+    async fn run_offline(root: &Path, args: &[&str]) -> error::Result<ExitCode> {
+        let (dir, cache_dir) = (root.join("repo"), root.join("cache"));
+        let mut argv = vec![
+            "flatpak_jetbrain_updater".as_ref(),
+            "--offline".as_ref(),
+            "--cache-dir".as_ref(),
+            cache_dir.as_os_str(),
+            "--dir".as_ref(),
+            dir.as_os_str(),
+        ];
+        argv.extend(args.iter().map(OsStr::new));
+        let cli = Cli::parse_from(argv);
+        execute(&cli, cli.command.clone().unwrap()).await
+    }
+
+    #[tokio::test]
+    async fn test_offline_update_from_cache() {
+        let root = offline_checkout("offline");
+        let dir = root.join("repo");
+        assert_eq!(
+            run_offline(&root, &["check"]).await.unwrap(),
+            ExitCode::from(UPDATE_AVAILABLE)
+        );
+        assert_eq!(
+            run_offline(&root, &["update"]).await.unwrap(),
+            ExitCode::SUCCESS
+        );
+        assert_eq!(
+            run_offline(&root, &["check"]).await.unwrap(),
+            ExitCode::SUCCESS
+        );
+
+        let manifest = std::fs::read_to_string(dir.join("com.jetbrains.WebStorm.yaml")).unwrap();
+        assert_eq!(manifest, UPDATED);
+        assert_eq!(read_appdata(&dir), updated_appdata());
+
+        // Up to date, so neither file is rendered and no checksum is requested
+        std::fs::remove_dir_all(root.join("cache/download.jetbrains.com")).unwrap();
+        assert_eq!(
+            run_offline(&root, &["update"]).await.unwrap(),
+            ExitCode::SUCCESS
+        );
+        let manifest = std::fs::read_to_string(dir.join("com.jetbrains.WebStorm.yaml")).unwrap();
+        assert_eq!(manifest, UPDATED);
+        assert_eq!(read_appdata(&dir), updated_appdata());
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_diff_writes_nothing() {
+        let root = offline_checkout("diff");
+        let dir = root.join("repo");
+        let listing = || {
+            let mut names = std::fs::read_dir(&dir)
+                .unwrap()
+                .map(|entry| entry.unwrap().file_name())
+                .collect::<Vec<_>>();
+            names.sort();
+            names
+        };
+        let before = listing();
+
+        // Not even `--backup` leaves a file behind
+        for args in [
+            &["diff"][..],
+            &["--backup", "diff"],
+            &["update", "--dry-run"],
+        ] {
+            assert_eq!(run_offline(&root, args).await.unwrap(), ExitCode::SUCCESS);
+            let manifest =
+                std::fs::read_to_string(dir.join("com.jetbrains.WebStorm.yaml")).unwrap();
+            assert_eq!(
+                manifest,
+                include_str!("../tests/fixtures/com.jetbrains.WebStorm_bak.yaml")
+            );
+            assert_eq!(read_appdata(&dir), APPDATA);
+            assert_eq!(listing(), before);
+        }
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_restore_the_last_backup() {
+        let root = offline_checkout("restore");
+        let dir = root.join("repo");
+        assert_eq!(
+            run_offline(&root, &["--backup", "update"]).await.unwrap(),
+            ExitCode::SUCCESS
+        );
+        let backups = std::fs::read_dir(&dir)
+            .unwrap()
+            .filter(|entry| entry.as_ref().unwrap().path().extension() == Some("bak".as_ref()))
+            .count();
+        assert_eq!(backups, 2);
+
+        // A broken manifest is what the backup is for
+        std::fs::write(dir.join("com.jetbrains.WebStorm.yaml"), "modules: [").unwrap();
+        assert!(run_offline(&root, &["check"]).await.is_err());
+        assert_eq!(
+            run_offline(&root, &["restore"]).await.unwrap(),
+            ExitCode::SUCCESS
+        );
+        let manifest = std::fs::read_to_string(dir.join("com.jetbrains.WebStorm.yaml")).unwrap();
+        assert_eq!(
+            manifest,
+            include_str!("../tests/fixtures/com.jetbrains.WebStorm_bak.yaml")
+        );
+        assert_eq!(read_appdata(&dir), APPDATA);
+        // Nothing but the two files is left behind
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 2);
+        assert!(run_offline(&root, &["restore"]).await.is_err());
+
+        // Explicit paths skip the detection, the manifest is not parsed either
+        run_offline(&root, &["--backup", "update"]).await.unwrap();
+        let manifest = dir.join("com.jetbrains.WebStorm.yaml");
+        std::fs::write(&manifest, "modules: [").unwrap();
+        let appdata = dir.join("com.jetbrains.WebStorm.appdata.xml");
+        let (manifest, appdata) = (manifest.to_str().unwrap(), appdata.to_str().unwrap());
+        let args = ["--manifest", manifest, "--appdata", appdata, "restore"];
+        assert_eq!(run_offline(&root, &args).await.unwrap(), ExitCode::SUCCESS);
+        assert_eq!(
+            std::fs::read_to_string(manifest).unwrap(),
+            include_str!("../tests/fixtures/com.jetbrains.WebStorm_bak.yaml")
+        );
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_rollback_to_previous_release() {
+        let root = offline_checkout("rollback");
+        let dir = root.join("repo");
+        let cache = Cache::new(root.join("cache"));
+        for (file, sha256) in [
+            (
+                "WebStorm-2024.3.5.tar.gz",
+                "da587d7ca3ebb08f067143e4a6b35f1aa133aa10af7fc365496838006fcd1aed",
+            ),
+            (
+                "WebStorm-2024.3.5-aarch64.tar.gz",
+                "fce5d5c2b8c5aacfabac60ff93b93d7c9a3239adcf8347b3deabd472ac1c1288",
+            ),
+        ] {
+            let url = format!("https://download.jetbrains.com/webstorm/{}.sha256", file);
+            cache
+                .write(&url, &format!("{} *{}\n", sha256, file))
+                .unwrap();
+        }
+        assert_eq!(
+            run_offline(&root, &["update"]).await.unwrap(),
+            ExitCode::SUCCESS
+        );
+
+        assert_eq!(
+            run_offline(&root, &["rollback"]).await.unwrap(),
+            ExitCode::SUCCESS
+        );
+        let manifest = std::fs::read_to_string(dir.join("com.jetbrains.WebStorm.yaml")).unwrap();
+        assert_eq!(
+            manifest,
+            include_str!("../tests/fixtures/com.jetbrains.WebStorm_bak.yaml")
+        );
+        assert_eq!(read_appdata(&dir), APPDATA);
+
+        // Going forward is what `update` is for
+        assert!(run_offline(&root, &["rollback", "2025.1"]).await.is_err());
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_min_age_holds_back_new_releases() {
+        let root = offline_checkout("min-age");
+        let dir = root.join("repo");
+        // One day short of adopting 2025.1, the manifest already is on the next older 2024.3.5
+        let response: ReleasesResponse = serde_json::from_str(RELEASES).unwrap();
+        let collection = read_json(&response["WS"]).unwrap();
+        let min_age = (release_age(&collection[0], today()).unwrap() + 1).to_string();
+        let args = ["--min-age", min_age.as_str(), "--announce-held"];
+
+        assert_eq!(
+            run_offline(&root, &[&args[..], &["check"]].concat())
+                .await
+                .unwrap(),
+            ExitCode::SUCCESS
+        );
+        assert_eq!(
+            run_offline(&root, &[&args[..], &["update"]].concat())
+                .await
+                .unwrap(),
+            ExitCode::SUCCESS
+        );
+        let manifest = std::fs::read_to_string(dir.join("com.jetbrains.WebStorm.yaml")).unwrap();
+        assert_eq!(
+            manifest,
+            include_str!("../tests/fixtures/com.jetbrains.WebStorm_bak.yaml")
+        );
+        assert_eq!(read_appdata(&dir), updated_appdata());
+
+        // Once old enough, the release is adopted although the appdata already lists it
+        assert_eq!(
+            run_offline(&root, &["update"]).await.unwrap(),
+            ExitCode::SUCCESS
+        );
+        let manifest = std::fs::read_to_string(dir.join("com.jetbrains.WebStorm.yaml")).unwrap();
+        assert_eq!(manifest, UPDATED);
+        assert_eq!(read_appdata(&dir), updated_appdata());
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_update_commits_and_pushes_a_branch() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let root = offline_checkout("git");
+        let dir = root.join("repo");
+        let git = |dir: &Path, args: &[&str]| {
+            let output = std::process::Command::new("git")
+                .arg("-C")
+                .arg(dir)
+                .args(args)
+                .output()
+                .unwrap();
+            assert!(output.status.success(), "{:?}", output);
+            String::from_utf8(output.stdout).unwrap()
+        };
+        let remote = root.join("remote.git");
+        git(
+            &root,
+            &["init", "--quiet", "--bare", remote.to_str().unwrap()],
+        );
+        git(&dir, &["init", "--quiet"]);
+        git(&dir, &["config", "user.name", "Updater"]);
+        git(&dir, &["config", "user.email", "updater@example.com"]);
+        git(&dir, &["remote", "add", "origin", remote.to_str().unwrap()]);
+        git(&dir, &["add", "."]);
+        git(&dir, &["commit", "--quiet", "--message", "Initial"]);
+
+        // Other changes would end up in the commit
+        let appdata = dir.join("com.jetbrains.WebStorm.appdata.xml");
+        let original = std::fs::read_to_string(&appdata).unwrap();
+        std::fs::write(&appdata, format!("{}\n", original)).unwrap();
+        assert!(run_offline(&root, &["update", "--git"]).await.is_err());
+        std::fs::write(&appdata, original).unwrap();
+
+        // A taken branch is refused before anything is written
+        let head = git(&dir, &["rev-parse", "--abbrev-ref", "HEAD"]);
+        git(&dir, &["branch", "update-2025.1"]);
+        assert!(run_offline(&root, &["update", "--git"]).await.is_err());
+        assert!(git(&dir, &["status", "--porcelain"]).is_empty());
+        git(&dir, &["branch", "--delete", "update-2025.1"]);
+
+        // A failed commit reverts both files and drops the branch
+        let hook = dir.join(".git/hooks/pre-commit");
+        std::fs::write(&hook, "#!/bin/sh\nexit 1\n").unwrap();
+        std::fs::set_permissions(&hook, std::fs::Permissions::from_mode(0o755)).unwrap();
+        assert!(run_offline(&root, &["update", "--git"]).await.is_err());
+        assert!(git(&dir, &["status", "--porcelain"]).is_empty());
+        assert_eq!(git(&dir, &["rev-parse", "--abbrev-ref", "HEAD"]), head);
+        assert!(git(&dir, &["branch", "--list", "update-2025.1"]).is_empty());
+        std::fs::remove_file(&hook).unwrap();
+
+        // A held back release announced in the appdata alone is no update to commit, so it does
+        // not take the branch of the later one
+        let response: ReleasesResponse = serde_json::from_str(RELEASES).unwrap();
+        let collection = read_json(&response["WS"]).unwrap();
+        let min_age = (release_age(&collection[0], today()).unwrap() + 1).to_string();
+        let args = [
+            "--min-age",
+            min_age.as_str(),
+            "--announce-held",
+            "update",
+            "--git",
+        ];
+        assert_eq!(run_offline(&root, &args).await.unwrap(), ExitCode::SUCCESS);
+        assert_eq!(read_appdata(&dir), updated_appdata());
+        assert!(git(&dir, &["branch", "--list", "update-2025.1"]).is_empty());
+        assert_eq!(git(&dir, &["rev-parse", "--abbrev-ref", "HEAD"]), head);
+        git(&dir, &["checkout", "--quiet", "--", "."]);
+
+        assert_eq!(
+            run_offline(&root, &["update", "--git", "--push"])
+                .await
+                .unwrap(),
+            ExitCode::SUCCESS
+        );
+        let message = git(&remote, &["log", "-1", "--format=%B", "update-2025.1"]);
+        assert!(message.starts_with("Update WebStorm to 2025.1\n"));
+        assert!(message.contains("WebStorm 2024.3.5 -> 2025.1, build 251.23774.424"));
+        assert!(git(&dir, &["status", "--porcelain"]).is_empty());
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_pr_body_lists_downloads_and_skipped_releases() {
+        let root = offline_checkout("pr-body");
+        let output = root.join("body.md");
+        assert_eq!(
+            run_offline(
+                &root,
+                &[
+                    "pr-body",
+                    "--from",
+                    "2024.3.4",
+                    "--output",
+                    output.to_str().unwrap()
+                ]
+            )
+            .await
+            .unwrap(),
+            ExitCode::SUCCESS
+        );
+        let body = std::fs::read_to_string(&output).unwrap();
+        assert!(body.starts_with("## Update WebStorm from 2024.3.4 to 2025.1\n"));
+        assert!(
+            body.contains(
+                "sha256 `51fd7420fb49ff8851d3b4b1079f8d7afebe9631a76e7d1d4fd3e66d42332c6c`"
+            )
+        );
+        assert!(body.contains("\n- 2024.3.5 (2025-03-13)"));
+        assert!(!body.contains("2024.3.4 ("));
+
+        // Once the manifest is written, the version before the update has to be given
+        run_offline(&root, &["update"]).await.unwrap();
+        assert!(run_offline(&root, &["pr-body"]).await.is_err());
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,221 @@
+use crate::error;
+use crate::resolve::PolicyDef;
+use serde::Deserialize;
+use snafu::{ResultExt, whatever};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// The registry shipped with the binary, see the file for the meaning of each field.
+const BUILTIN_PRODUCTS: &str = include_str!("resolve/products.toml");
+
+/// Directory name under `$XDG_CONFIG_HOME` that is searched when `--config` is omitted.
+const CONFIG_DIR: &str = "flatpak-jetbrain-updater";
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ProductDef {
+    pub short: String,
+    pub lowercase: String,
+    pub name: String,
+    pub display_name: String,
+    pub code: String,
+    pub tarball: String,
+    pub archive: String,
+}
+
+/// How requests to the JetBrains servers are made, every field is optional in the config.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct HttpConfig {
+    /// Seconds to wait for a connection to be established.
+    pub connect_timeout: u64,
+    /// Seconds to wait for the next bytes of a response, tarballs are streamed so there is no total limit.
+    pub read_timeout: u64,
+    /// Attempts per request, including the first one.
+    pub attempts: u32,
+    /// Delay before the first retry in milliseconds, doubled on every further retry.
+    pub backoff: u64,
+    pub user_agent: String,
+    /// Proxy for every request, `HTTPS_PROXY` and friends are honored when omitted.
+    pub proxy: Option<String>,
+    /// Replaces `https://data.services.jetbrains.com`.
+    pub api_base_url: String,
+    /// Replaces `https://download.jetbrains.com` when downloading, the manifest keeps the original links.
+    pub download_base_url: String,
+}
+
+impl Default for HttpConfig {
+    fn default() -> HttpConfig {
+        HttpConfig {
+            connect_timeout: 10,
+            read_timeout: 30,
+            attempts: 3,
+            backoff: 1000,
+            user_agent: concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION")).to_string(),
+            proxy: None,
+            api_base_url: DEFAULT_API_BASE_URL.to_string(),
+            download_base_url: DEFAULT_DOWNLOAD_BASE_URL.to_string(),
+        }
+    }
+}
+
+/// How `update --git` names the branch and writes the commit, every field is optional in the config.
+///
+/// `branch` and `message` may contain `{product}`, `{old}`, `{new}`, `{build}` and `{notes}`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct GitConfig {
+    pub branch: String,
+    pub message: String,
+    /// Where `--push` sends the branch.
+    pub remote: String,
+}
+
+impl Default for GitConfig {
+    fn default() -> GitConfig {
+        GitConfig {
+            branch: "update-{new}".to_string(),
+            message: "Update {product} to {new}\n\n{product} {old} -> {new}, build {build}\nRelease notes: {notes}\n"
+                .to_string(),
+            remote: "origin".to_string(),
+        }
+    }
+}
+
+pub const DEFAULT_API_BASE_URL: &str = "https://data.services.jetbrains.com";
+pub const DEFAULT_DOWNLOAD_BASE_URL: &str = "https://download.jetbrains.com";
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Config {
+    pub products: Vec<ProductDef>,
+    /// Only taken from the user config, the built-in registry has no opinion about it.
+    pub http: HttpConfig,
+    /// Only taken from the user config as well.
+    pub git: GitConfig,
+    /// Update policy per product `short`, products without one follow the latest release.
+    pub policy: HashMap<String, PolicyDef>,
+}
+
+impl Config {
+    /// Loads the built-in registry and merges the user config on top of it.
+    ///
+    /// An explicit `path` has to exist, otherwise the default location is used if present.
+    pub fn load(path: Option<&Path>) -> error::Result<Config> {
+        let mut config = Self::builtin()?;
+        let user = match path {
+            Some(path) => Some(Self::from_file(path)?),
+            None => Self::default_path()
+                .map(|path| Self::from_file(&path))
+                .transpose()?,
+        };
+        if let Some(user) = user {
+            config.merge(user);
+        }
+        Ok(config)
+    }
+
+    #[inline]
+    pub(crate) fn builtin() -> error::Result<Config> {
+        toml::from_str(BUILTIN_PRODUCTS)
+            .whatever_context("Failed to parse built-in products, this is a bug")
+    }
+
+    fn from_file(path: &Path) -> error::Result<Config> {
+        let content = std::fs::read_to_string(path).with_whatever_context(|e| {
+            format!("Failed to read config at {}, source: {:?}", path.display(), e)
+        })?;
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => toml::from_str(&content).with_whatever_context(|e| {
+                format!("Failed to parse config at {}, source: {}", path.display(), e)
+            }),
+            Some("yaml" | "yml") => serde_yaml::from_str(&content).with_whatever_context(|e| {
+                format!("Failed to parse config at {}, source: {}", path.display(), e)
+            }),
+            _ => whatever!(
+                "Unsupported config format at {}, expected .toml, .yaml or .yml",
+                path.display()
+            ),
+        }
+    }
+
+    fn default_path() -> Option<PathBuf> {
+        let dir = std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?
+            .join(CONFIG_DIR);
+        ["config.toml", "config.yaml", "config.yml"]
+            .iter()
+            .map(|name| dir.join(name))
+            .find(|path| path.is_file())
+    }
+
+    /// User entries replace built-in ones with the same `short`, others are appended.
+    fn merge(&mut self, user: Config) {
+        self.http = user.http;
+        self.git = user.git;
+        self.policy.extend(user.policy);
+        for product in user.products {
+            match self.products.iter_mut().find(|p| p.short == product.short) {
+                Some(existing) => *existing = product,
+                None => self.products.push(product),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resolve::Policy;
+
+    #[test]
+    fn test_user_products_override_builtin() {
+        let mut config = Config::builtin().unwrap();
+        let builtin = config.products.len();
+        let user: Config = serde_yaml::from_str(
+            r#"
+products:
+  - short: webstorm
+    lowercase: webstorm
+    name: WebStorm
+    display-name: WebStorm EAP
+    code: WS
+    tarball: webstorm.tar.gz
+    archive: WebStorm-{version}{arch}.tar.gz
+  - short: aqua
+    lowercase: aqua
+    name: Aqua
+    display-name: Aqua
+    code: QA
+    tarball: aqua.tar.gz
+    archive: aqua-{version}{arch}.tar.gz
+"#,
+        )
+        .unwrap();
+        config.merge(user);
+        assert_eq!(config.products.len(), builtin + 1);
+        let webstorm = config.products.iter().find(|p| p.short == "webstorm");
+        assert_eq!(webstorm.unwrap().display_name, "WebStorm EAP");
+    }
+
+    #[test]
+    fn test_policy_per_product() {
+        let config: Config = toml::from_str(
+            r#"
+[policy.webstorm]
+track = "2024.3"
+
+[policy.clion]
+pin = "2025.1"
+track = "2025.1"
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.policy["webstorm"].to_policy().unwrap(),
+            Policy::Track("2024.3".to_string())
+        );
+        assert!(config.policy["clion"].to_policy().is_err());
+    }
+}
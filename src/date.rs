@@ -0,0 +1,90 @@
+//! Calendar dates in UTC without a time zone database, in days since the Unix epoch.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Days since the Unix epoch in UTC.
+pub fn today() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| (now.as_secs() / 86400) as i64)
+}
+
+/// Days since the Unix epoch of a `YYYY-MM-DD` date.
+pub fn parse_date(date: &str) -> Option<i64> {
+    let mut parts = date.splitn(3, '-');
+    let year: i64 = parts.next()?.parse().ok()?;
+    let month: i64 = parts.next()?.parse().ok()?;
+    let day: i64 = parts.next()?.parse().ok()?;
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    // The proleptic Gregorian calendar with the year starting in March, so that
+    // the leap day is the last day of the year
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    Some(era * 146097 + day_of_era - 719468)
+}
+
+/// The `YYYY-MM-DD` date of days since the Unix epoch, the inverse of [`parse_date`].
+fn format_date(days: i64) -> String {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// The current time as `YYYYMMDDTHHMMSSZ`, which sorts in time order.
+pub fn timestamp() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| now.as_secs() as i64);
+    format_timestamp(secs)
+}
+
+fn format_timestamp(secs: i64) -> String {
+    let (days, secs) = (secs.div_euclid(86400), secs.rem_euclid(86400));
+    format!(
+        "{}T{:02}{:02}{:02}Z",
+        format_date(days).replace('-', ""),
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_date() {
+        assert_eq!(parse_date("1970-01-01"), Some(0));
+        assert_eq!(parse_date("2000-03-01"), Some(11017));
+        assert_eq!(parse_date("2025-04-16"), Some(20194));
+        assert_eq!(
+            parse_date("2024-02-29").map(|day| day + 1),
+            parse_date("2024-03-01")
+        );
+        assert_eq!(parse_date("2025-13-01"), None);
+        assert_eq!(parse_date(""), None);
+    }
+
+    #[test]
+    fn test_format_timestamp() {
+        assert_eq!(format_timestamp(0), "19700101T000000Z");
+        assert_eq!(format_timestamp(1_744_800_000), "20250416T104000Z");
+        for date in ["2000-02-29", "2024-12-31", "2025-03-01"] {
+            assert_eq!(format_date(parse_date(date).unwrap()), date);
+        }
+    }
+}
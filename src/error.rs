@@ -5,8 +5,18 @@ pub type Result<T> = std::result::Result<T, Error>;
 #[derive(Debug, Snafu)]
 #[snafu(visibility(pub(crate)))]
 pub enum Error {
-    #[snafu(display("Network Error: {message}"))]
-    Network { message: String },
+    #[snafu(display(
+        "Failed to request {url} after {attempts} attempt(s), {}: {message}",
+        status.map_or_else(|| "no response".to_string(), |s| format!("HTTP {}", s))
+    ))]
+    Http {
+        url: String,
+        status: Option<u16>,
+        attempts: u32,
+        message: String,
+    },
+    #[snafu(display("Invalid checksum from {url}: {reason}"))]
+    ChecksumFormat { url: String, reason: String },
     #[snafu(whatever, display("{message}"))]
     Whatever {
         message: String,
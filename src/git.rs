@@ -0,0 +1,157 @@
+use crate::error;
+use snafu::{ResultExt, whatever};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+/// The git checkout holding the manifest, driven through the `git` command line.
+pub struct Git {
+    dir: PathBuf,
+}
+
+impl Git {
+    /// The checkout which contains `dir`, an error if there is none.
+    pub fn open(dir: &Path) -> error::Result<Git> {
+        let dir = if dir.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            dir
+        };
+        let top = Git {
+            dir: dir.to_path_buf(),
+        }
+        .run(&["rev-parse", "--show-toplevel"])?;
+        Ok(Git {
+            dir: PathBuf::from(top.trim_end()),
+        })
+    }
+
+    /// Refuses a checkout with staged or unstaged changes to tracked files, untracked files,
+    /// e.g. backups, do not matter.
+    pub fn ensure_clean(&self) -> error::Result<()> {
+        let status = self.run(&["status", "--porcelain", "--untracked-files=no"])?;
+        if !status.trim().is_empty() {
+            whatever!(
+                "The git checkout at {} has uncommitted changes, commit or stash them first:\n{}",
+                self.dir.display(),
+                status.trim_end()
+            );
+        }
+        Ok(())
+    }
+
+    /// Refuses a `branch` which exists already, as switching to it would fail after the write.
+    pub fn ensure_new_branch(&self, branch: &str) -> error::Result<()> {
+        let reference = format!("refs/heads/{}", branch);
+        if self
+            .output(&["rev-parse", "--verify", "--quiet", &reference])?
+            .status
+            .success()
+        {
+            whatever!(
+                "The branch {} already exists in {}, delete it or change the `[git]` branch template",
+                branch,
+                self.dir.display()
+            );
+        }
+        Ok(())
+    }
+
+    /// Refuses a checkout without `user.name` and `user.email`, which a commit needs.
+    pub fn ensure_identity(&self) -> error::Result<()> {
+        for ident in ["GIT_AUTHOR_IDENT", "GIT_COMMITTER_IDENT"] {
+            if !self.output(&["var", ident])?.status.success() {
+                whatever!(
+                    "git has no identity to commit with in {}, set user.name and user.email first",
+                    self.dir.display()
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Creates `branch` from the current commit and switches to it.
+    pub fn create_branch(&self, branch: &str) -> error::Result<()> {
+        self.run(&["switch", "--create", branch]).map(|_| ())
+    }
+
+    /// Commits exactly `paths`, whatever else is staged stays out of the commit.
+    ///
+    /// If the commit fails, `paths` are unstaged again.
+    pub fn commit(&self, paths: &[&Path], message: &str) -> error::Result<()> {
+        let paths = paths
+            .iter()
+            .map(|path| std::path::absolute(path).map(|path| path.to_string_lossy().into_owned()))
+            .collect::<std::io::Result<Vec<String>>>()
+            .whatever_context("Failed to resolve the paths to commit")?;
+        let mut add = vec!["add", "--"];
+        add.extend(paths.iter().map(String::as_str));
+        self.run(&add)?;
+        let mut commit = vec!["commit", "--quiet", "--message", message, "--"];
+        commit.extend(paths.iter().map(String::as_str));
+        if let Err(e) = self.run(&commit) {
+            // A failed commit, e.g. by a hook, leaves nothing staged behind
+            let mut reset = vec!["reset", "--quiet", "--"];
+            reset.extend(paths.iter().map(String::as_str));
+            let _ = self.run(&reset);
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Switches back to the previous branch and deletes `branch`, undoing [`Git::create_branch`].
+    pub fn discard_branch(&self, branch: &str) -> error::Result<()> {
+        self.run(&["switch", "-"])?;
+        self.run(&["branch", "--delete", "--force", branch])
+            .map(|_| ())
+    }
+
+    pub fn push(&self, remote: &str, branch: &str) -> error::Result<()> {
+        self.run(&["push", "--quiet", "--set-upstream", remote, branch])
+            .map(|_| ())
+    }
+
+    fn output(&self, args: &[&str]) -> error::Result<Output> {
+        Command::new("git")
+            .arg("-C")
+            .arg(&self.dir)
+            .args(args)
+            .output()
+            .with_whatever_context(|e| format!("Failed to run git, is it installed? source: {}", e))
+    }
+
+    fn run(&self, args: &[&str]) -> error::Result<String> {
+        let output = self.output(args)?;
+        if !output.status.success() {
+            whatever!(
+                "`git {}` failed in {}: {}",
+                args.first().copied().unwrap_or_default(),
+                self.dir.display(),
+                String::from_utf8_lossy(&output.stderr).trim_end()
+            );
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+}
+
+/// Fills the `{name}` placeholders of a branch or message template.
+pub fn render_template(template: &str, values: &[(&str, &str)]) -> String {
+    values
+        .iter()
+        .fold(template.to_string(), |text, (name, value)| {
+            text.replace(&format!("{{{}}}", name), value)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_template() {
+        let values = [("product", "WebStorm"), ("new", "2025.1")];
+        assert_eq!(
+            render_template("Update {product} to {new}, {unknown}", &values),
+            "Update WebStorm to 2025.1, {unknown}"
+        );
+    }
+}
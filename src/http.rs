@@ -0,0 +1,428 @@
+use crate::cache::{Cache, Validators};
+use crate::config::{DEFAULT_API_BASE_URL, DEFAULT_DOWNLOAD_BASE_URL, HttpConfig};
+use crate::error;
+use crate::error::Error;
+use reqwest::header::{ETAG, HeaderValue, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use reqwest::{Client, Proxy, Response, StatusCode};
+use snafu::{OptionExt, ResultExt, whatever};
+use std::hash::{BuildHasher, RandomState};
+use std::time::Duration;
+
+/// The one client used for every request, it knows about retries, mirrors and the cache.
+///
+/// URLs are always given in their canonical form, i.e. on the JetBrains servers,
+/// so that the cache does not depend on the mirror in use.
+pub struct Http {
+    client: Client,
+    attempts: u32,
+    backoff: Duration,
+    api_base_url: String,
+    download_base_url: String,
+    cache: Option<Cache>,
+    offline: bool,
+}
+
+impl Http {
+    /// Text responses are stored in `cache`, with `offline` they are only read from it.
+    pub fn new(config: &HttpConfig, cache: Option<Cache>, offline: bool) -> error::Result<Http> {
+        if offline && cache.is_none() {
+            whatever!("Offline mode needs a cache directory, please set --cache-dir");
+        }
+        let mut builder = Client::builder()
+            .connect_timeout(Duration::from_secs(config.connect_timeout))
+            .read_timeout(Duration::from_secs(config.read_timeout))
+            .user_agent(config.user_agent.as_str());
+        if let Some(proxy) = &config.proxy {
+            builder = builder
+                .proxy(Proxy::all(proxy).with_whatever_context(|e| {
+                    format!("Invalid proxy {}, source: {}", proxy, e)
+                })?);
+        }
+        Ok(Http {
+            client: builder
+                .build()
+                .whatever_context("Failed to build the HTTP client")?,
+            attempts: config.attempts.max(1),
+            backoff: Duration::from_millis(config.backoff),
+            api_base_url: config.api_base_url.trim_end_matches('/').to_string(),
+            download_base_url: config.download_base_url.trim_end_matches('/').to_string(),
+            cache,
+            offline,
+        })
+    }
+
+    /// The canonical address of `path` on the releases API, e.g. `/products/releases?code=WS`.
+    #[inline]
+    pub fn api_url(path: &str) -> String {
+        format!("{}{}", DEFAULT_API_BASE_URL, path)
+    }
+
+    #[inline]
+    pub fn is_offline(&self) -> bool {
+        self.offline
+    }
+
+    #[inline]
+    pub fn cache(&self) -> Option<&Cache> {
+        self.cache.as_ref()
+    }
+
+    /// Where to actually request `url` from, honoring the configured base URLs.
+    pub fn resolve(&self, url: &str) -> String {
+        if let Some(rest) = url.strip_prefix(DEFAULT_API_BASE_URL) {
+            format!("{}{}", self.api_base_url, rest)
+        } else if let Some(rest) = url.strip_prefix(DEFAULT_DOWNLOAD_BASE_URL) {
+            format!("{}{}", self.download_base_url, rest)
+        } else {
+            url.to_string()
+        }
+    }
+
+    /// Requests `url` until it answers with a success status, the response is never cached.
+    #[inline]
+    pub async fn get(&self, url: &str) -> error::Result<Response> {
+        self.send(url, None).await
+    }
+
+    /// Connection errors, timeouts, `429` and `5xx` are retried with exponential backoff
+    /// and jitter, any other status fails immediately.
+    ///
+    /// With `validators` the request is conditional and `304` counts as success.
+    async fn send(&self, url: &str, validators: Option<&Validators>) -> error::Result<Response> {
+        if self.offline {
+            whatever!("Can not request {} in offline mode", url);
+        }
+        let resolved = self.resolve(url);
+        let mut status = None;
+        let mut message = String::new();
+        let mut attempt = 0;
+        while attempt < self.attempts {
+            if attempt > 0 {
+                tokio::time::sleep(self.delay(attempt)).await;
+            }
+            attempt += 1;
+            let mut request = self.client.get(&resolved);
+            if let Some(validators) = validators {
+                if let Some(etag) = &validators.etag {
+                    request = request.header(IF_NONE_MATCH, etag);
+                }
+                if let Some(last_modified) = &validators.last_modified {
+                    request = request.header(IF_MODIFIED_SINCE, last_modified);
+                }
+            }
+            match request.send().await {
+                Ok(response)
+                    if response.status().is_success()
+                        || (validators.is_some()
+                            && response.status() == StatusCode::NOT_MODIFIED) =>
+                {
+                    return Ok(response);
+                }
+                Ok(response) => {
+                    let code = response.status();
+                    status = Some(code.as_u16());
+                    message = code
+                        .canonical_reason()
+                        .unwrap_or("unexpected status")
+                        .to_string();
+                    if !Self::is_transient(code) {
+                        break;
+                    }
+                }
+                Err(e) => {
+                    status = None;
+                    message = e.to_string();
+                }
+            }
+        }
+        Err(Error::Http {
+            url: url.to_string(),
+            status,
+            attempts: attempt,
+            message,
+        })
+    }
+
+    /// Like [`Http::get`], but reads the whole body as text and goes through the cache.
+    ///
+    /// A cached body is revalidated with `If-None-Match` / `If-Modified-Since`,
+    /// so an unchanged resource is not downloaded again.
+    pub async fn get_text(&self, url: &str) -> error::Result<String> {
+        if self.offline {
+            let cache = self
+                .cache
+                .as_ref()
+                .whatever_context("Offline mode needs a cache")?;
+            return cache.read(url)?.with_whatever_context(|| {
+                format!(
+                    "{} is not cached in {}, please run `fetch` while online first",
+                    url,
+                    cache.dir().display()
+                )
+            });
+        }
+        let cached = match &self.cache {
+            Some(cache) => cache.read_validators(url)?,
+            None => None,
+        };
+        let response = self.send(url, cached.as_ref()).await?;
+        if response.status() == StatusCode::NOT_MODIFIED {
+            let cache = self
+                .cache
+                .as_ref()
+                .whatever_context("Unexpected 304 without cache")?;
+            return cache.read(url)?.with_whatever_context(|| {
+                format!("{} was not modified, but its cached copy is gone", url)
+            });
+        }
+
+        let header = |name| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value: &HeaderValue| value.to_str().ok())
+                .map(str::to_owned)
+        };
+        let validators = Validators {
+            etag: header(ETAG),
+            last_modified: header(LAST_MODIFIED),
+        };
+        let body = response.text().await.with_whatever_context(|e| {
+            format!("Failed to read response from {}, source: {}", url, e)
+        })?;
+        if let Some(cache) = &self.cache {
+            cache.write(url, &body)?;
+            if !validators.is_empty() {
+                cache.write_validators(url, &validators)?;
+            }
+        }
+        Ok(body)
+    }
+
+    #[inline]
+    fn is_transient(status: StatusCode) -> bool {
+        status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+    }
+
+    /// `backoff * 2^(retry - 1)`, plus up to half of that as jitter.
+    fn delay(&self, retry: u32) -> Duration {
+        let base = self.backoff.saturating_mul(1 << (retry - 1).min(16));
+        let jitter = RandomState::new().hash_one(retry) % (base.as_millis() as u64 / 2 + 1);
+        base + Duration::from_millis(jitter)
+    }
+}
+
+/// A tiny HTTP server for tests, which answers every path with a scripted list of responses.
+#[cfg(test)]
+pub(crate) mod mock {
+    use super::Http;
+    use crate::config::HttpConfig;
+    use std::collections::HashMap;
+    use std::hash::{DefaultHasher, Hash, Hasher};
+    use std::sync::{Arc, Mutex};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    type Routes = HashMap<String, Vec<(u16, String)>>;
+
+    #[derive(Debug, Clone)]
+    pub(crate) struct Request {
+        pub path: String,
+        pub user_agent: String,
+        pub if_none_match: Option<String>,
+        pub status: u16,
+    }
+
+    pub(crate) struct MockServer {
+        pub url: String,
+        /// Every request, in order.
+        pub requests: Arc<Mutex<Vec<Request>>>,
+    }
+
+    impl MockServer {
+        /// Each path answers with its responses in order, repeating the last one,
+        /// unknown paths answer `404`.
+        ///
+        /// Successful responses carry an `ETag` of their body, and a matching
+        /// `If-None-Match` is answered with `304`.
+        pub async fn start(routes: &[(&str, &[(u16, &str)])]) -> MockServer {
+            let routes: Routes = routes
+                .iter()
+                .map(|(path, responses)| {
+                    let responses = responses.iter().map(|(s, b)| (*s, b.to_string())).collect();
+                    (path.to_string(), responses)
+                })
+                .collect();
+            let routes = Arc::new(Mutex::new(routes));
+            let requests = Arc::new(Mutex::new(Vec::new()));
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let url = format!("http://{}", listener.local_addr().unwrap());
+
+            let log = requests.clone();
+            tokio::spawn(async move {
+                loop {
+                    let Ok((mut stream, _)) = listener.accept().await else {
+                        break;
+                    };
+                    let mut buf = Vec::new();
+                    let mut chunk = [0u8; 1024];
+                    while !buf.windows(4).any(|w| w == b"\r\n\r\n") {
+                        match stream.read(&mut chunk).await {
+                            Ok(0) | Err(_) => break,
+                            Ok(n) => buf.extend_from_slice(&chunk[..n]),
+                        }
+                    }
+                    let request = String::from_utf8_lossy(&buf);
+                    let path = request.split_whitespace().nth(1).unwrap_or("/").to_string();
+                    let path = path.split('?').next().unwrap_or_default().to_string();
+                    let header = |name: &str| {
+                        request.lines().find_map(|line| {
+                            let (key, value) = line.split_once(": ")?;
+                            key.eq_ignore_ascii_case(name).then(|| value.to_string())
+                        })
+                    };
+                    let user_agent = header("user-agent").unwrap_or_default();
+                    let if_none_match = header("if-none-match");
+
+                    let (mut status, mut body) = {
+                        let mut routes = routes.lock().unwrap();
+                        match routes.get_mut(&path) {
+                            Some(responses) if responses.len() > 1 => responses.remove(0),
+                            Some(responses) => responses[0].clone(),
+                            None => (404, "Not Found".to_string()),
+                        }
+                    };
+                    let mut hasher = DefaultHasher::new();
+                    body.hash(&mut hasher);
+                    let etag = format!("\"{:x}\"", hasher.finish());
+                    if status == 200 && if_none_match.as_ref() == Some(&etag) {
+                        (status, body) = (304, String::new());
+                    }
+                    log.lock().unwrap().push(Request {
+                        path: path.clone(),
+                        user_agent,
+                        if_none_match,
+                        status,
+                    });
+                    let response = format!(
+                        "HTTP/1.1 {} Mock\r\nETag: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        status,
+                        etag,
+                        body.len(),
+                        body
+                    );
+                    let _ = stream.write_all(response.as_bytes()).await;
+                }
+            });
+            MockServer { url, requests }
+        }
+
+        /// A client pointed at this server for both the API and the downloads.
+        pub fn http(&self) -> Http {
+            Http::new(&self.config(), None, false).unwrap()
+        }
+
+        pub fn config(&self) -> HttpConfig {
+            HttpConfig {
+                backoff: 1,
+                proxy: None,
+                api_base_url: self.url.clone(),
+                download_base_url: self.url.clone(),
+                ..HttpConfig::default()
+            }
+        }
+
+        pub fn paths(&self) -> Vec<String> {
+            self.requests
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|request| request.path.clone())
+                .collect()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::mock::MockServer;
+    use super::*;
+
+    #[tokio::test]
+    async fn test_retries_transient_failures() {
+        let server = MockServer::start(&[
+            ("/flaky", &[(503, ""), (200, "ok")]),
+            ("/missing", &[(404, "")]),
+        ])
+        .await;
+        let http = server.http();
+
+        assert_eq!(http.get_text(&Http::api_url("/flaky")).await.unwrap(), "ok");
+        match http.get(&Http::api_url("/missing")).await {
+            Err(Error::Http {
+                status, attempts, ..
+            }) => {
+                assert_eq!(status, Some(404));
+                assert_eq!(attempts, 1);
+            }
+            _ => panic!("expected an HTTP error"),
+        }
+        assert_eq!(server.paths(), ["/flaky", "/flaky", "/missing"]);
+        let user_agent = &server.requests.lock().unwrap()[0].user_agent;
+        assert!(user_agent.starts_with(env!("CARGO_PKG_NAME")));
+    }
+
+    #[test]
+    fn test_resolve_uses_mirror() {
+        let config = HttpConfig {
+            download_base_url: "https://mirror.example.com/jetbrains/".to_string(),
+            ..HttpConfig::default()
+        };
+        let http = Http::new(&config, None, false).unwrap();
+        assert_eq!(
+            http.resolve("https://download.jetbrains.com/webstorm/WebStorm-2025.1.tar.gz"),
+            "https://mirror.example.com/jetbrains/webstorm/WebStorm-2025.1.tar.gz"
+        );
+        assert_eq!(
+            http.resolve("https://example.com/a"),
+            "https://example.com/a"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_offline_reads_what_was_fetched() {
+        let server = MockServer::start(&[("/products/releases", &[(200, "[]")])]).await;
+        let dir = std::env::temp_dir().join(format!("fju-http-{}", std::process::id()));
+        let url = Http::api_url("/products/releases?code=WS");
+
+        let online = Http::new(&server.config(), Some(Cache::new(dir.clone())), false).unwrap();
+        assert_eq!(online.get_text(&url).await.unwrap(), "[]");
+        let offline = Http::new(&server.config(), Some(Cache::new(dir.clone())), true).unwrap();
+        assert_eq!(offline.get_text(&url).await.unwrap(), "[]");
+        assert!(offline.get_text(&Http::api_url("/other")).await.is_err());
+        assert!(offline.get(&url).await.is_err());
+        assert_eq!(server.paths().len(), 1);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_revalidates_cached_bodies() {
+        let server =
+            MockServer::start(&[("/products/releases", &[(200, "v1"), (200, "v2")])]).await;
+        let dir = std::env::temp_dir().join(format!("fju-etag-{}", std::process::id()));
+        let http = Http::new(&server.config(), Some(Cache::new(dir.clone())), false).unwrap();
+        let url = Http::api_url("/products/releases?code=WS");
+
+        assert_eq!(http.get_text(&url).await.unwrap(), "v1");
+        assert_eq!(http.get_text(&url).await.unwrap(), "v2");
+        assert_eq!(http.get_text(&url).await.unwrap(), "v2");
+        let requests = server.requests.lock().unwrap().clone();
+        let statuses = requests.iter().map(|r| r.status).collect::<Vec<u16>>();
+        assert_eq!(statuses, [200, 200, 304]);
+        assert!(requests[0].if_none_match.is_none());
+        assert!(requests[1].if_none_match.is_some());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,8 +1,17 @@
+mod api;
+mod checksum;
+mod policy;
 mod product;
 mod release;
+mod version;
 
+pub use api::{Download, ReleaseRecord, ReleasesResponse};
+pub use checksum::{HashAlgorithm, parse_sidecar};
+pub use policy::{Cooldown, Policy, PolicyDef, release_age};
 pub use product::ProductInfo;
+pub use release::Arch;
 pub use release::Checksum;
 pub use release::Platform;
 pub use release::ProductRelease;
-pub use release::RE;
+pub use release::ReleaseType;
+pub use version::compare_release;
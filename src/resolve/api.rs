@@ -0,0 +1,118 @@
+use crate::resolve::{Arch, ReleaseType};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// Body of `products/releases`, the records of each requested product code.
+pub type ReleasesResponse = HashMap<String, Vec<ReleaseRecord>>;
+
+/// One release of a product as returned by the JetBrains API.
+///
+/// Fields which are not described here are ignored, so new additions on the server side
+/// do not break the parsing.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReleaseRecord {
+    pub date: String,
+    #[serde(rename = "type")]
+    pub release_type: ReleaseType,
+    pub version: String,
+    pub major_version: Option<String>,
+    pub build: Option<String>,
+    pub notes_link: Option<String>,
+    // Modelled for completeness, not read yet
+    #[allow(dead_code)]
+    pub whatsnew: Option<String>,
+    #[allow(dead_code)]
+    pub license_required: Option<bool>,
+    #[serde(default)]
+    pub downloads: Downloads,
+    #[allow(dead_code)]
+    #[serde(default)]
+    pub patches: Patches,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Downloads {
+    pub linux: Option<Download>,
+    #[serde(rename = "linuxARM64")]
+    pub linux_arm64: Option<Download>,
+    // Only the Linux downloads end up in a Flatpak manifest
+    #[allow(dead_code)]
+    pub windows: Option<Download>,
+    #[allow(dead_code)]
+    pub windows_zip: Option<Download>,
+    #[allow(dead_code)]
+    #[serde(rename = "windowsARM64")]
+    pub windows_arm64: Option<Download>,
+    #[allow(dead_code)]
+    pub mac: Option<Download>,
+    #[allow(dead_code)]
+    #[serde(rename = "macM1")]
+    pub mac_m1: Option<Download>,
+    #[allow(dead_code)]
+    pub third_party_libraries_json: Option<Download>,
+}
+
+impl Downloads {
+    /// The Linux download of `arch`, the one keyed by [`Arch::api_key`].
+    #[inline]
+    pub fn linux_of(&self, arch: Arch) -> Option<&Download> {
+        match arch {
+            Arch::X86_64 => self.linux.as_ref(),
+            Arch::Aarch64 => self.linux_arm64.as_ref(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Download {
+    pub link: String,
+    pub size: u64,
+    pub checksum_link: Option<String>,
+}
+
+/// Patches from older builds, grouped by the platform family they apply to.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Patches {
+    pub win: Vec<Patch>,
+    pub mac: Vec<Patch>,
+    pub unix: Vec<Patch>,
+}
+
+// Reached through `ReleaseRecord::patches` only, which is not read yet
+#[allow(dead_code)]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Patch {
+    pub from_build: String,
+    pub link: String,
+    pub size: u64,
+    pub checksum_link: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_release_record() {
+        let body = include_str!("../../tests/fixtures/releases_WS.json");
+        let response: ReleasesResponse = serde_json::from_str(body).unwrap();
+        let record = &response["WS"][0];
+        assert_eq!(record.version, "2025.1");
+        assert_eq!(record.release_type, ReleaseType::Release);
+        assert_eq!(record.build.as_deref(), Some("251.23774.424"));
+        assert_eq!(record.major_version.as_deref(), Some("2025.1"));
+        assert_eq!(record.license_required, Some(true));
+        let linux = record.downloads.linux.as_ref().unwrap();
+        assert_eq!(linux.size, 1101448284);
+        assert!(record.downloads.linux_arm64.is_some());
+        assert_eq!(record.patches.unix[0].from_build, "243.24978.79");
+        assert!(record.patches.mac.is_empty());
+        // Missing optional fields and unknown ones are both fine
+        assert!(response["WS"][2].notes_link.is_none());
+    }
+}
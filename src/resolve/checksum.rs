@@ -0,0 +1,122 @@
+use std::fmt;
+
+/// Hash algorithms which can be read from a sidecar and written into a Flatpak source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashAlgorithm {
+    Sha256,
+    Sha512,
+}
+
+impl HashAlgorithm {
+    /// Guesses the algorithm from the extension of a checksum link, e.g. `.tar.gz.sha256`.
+    #[inline]
+    pub fn from_link(link: &str) -> Option<HashAlgorithm> {
+        match link.rsplit('.').next()? {
+            "sha256" => Some(HashAlgorithm::Sha256),
+            "sha512" => Some(HashAlgorithm::Sha512),
+            _ => None,
+        }
+    }
+
+    /// The key of the checksum in a Flatpak source.
+    #[inline]
+    pub fn key(self) -> &'static str {
+        match self {
+            HashAlgorithm::Sha256 => "sha256",
+            HashAlgorithm::Sha512 => "sha512",
+        }
+    }
+
+    #[inline]
+    pub fn hex_len(self) -> usize {
+        match self {
+            HashAlgorithm::Sha256 => 64,
+            HashAlgorithm::Sha512 => 128,
+        }
+    }
+}
+
+impl fmt::Display for HashAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.key())
+    }
+}
+
+/// Parses a `<hash> *<filename>` sidecar as written by `sha256sum -b`.
+///
+/// A bare `<hash>` is accepted as well, but a filename other than `file_name` is refused,
+/// the hash is returned in lowercase.
+pub fn parse_sidecar(
+    body: &str,
+    algorithm: HashAlgorithm,
+    file_name: &str,
+) -> Result<String, String> {
+    let line = body
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .ok_or_else(|| "the checksum file is empty".to_string())?;
+    let (hash, name) = match line.split_once(char::is_whitespace) {
+        Some((hash, name)) => (hash, Some(name.trim_start())),
+        None => (line, None),
+    };
+
+    if hash.len() != algorithm.hex_len() || !hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(format!(
+            "expected {} hex digits of {}, found {:?}",
+            algorithm.hex_len(),
+            algorithm,
+            hash.chars()
+                .take(algorithm.hex_len() + 8)
+                .collect::<String>()
+        ));
+    }
+    // `*` marks binary mode, a space text mode
+    if let Some(name) = name.map(|name| name.strip_prefix('*').unwrap_or(name))
+        && name != file_name
+    {
+        return Err(format!("it is for {}, not {}", name, file_name));
+    }
+    Ok(hash.to_ascii_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_sidecar() {
+        let sha256 = "f181eb1348c04a60f860fd9ea9616261c6c012a7916e76380652d129e87503ea";
+        let file = "WebStorm-2025.1.tar.gz";
+        let parse = |body: &str, algorithm| parse_sidecar(body, algorithm, file);
+
+        let body = format!("{} *{}\n", sha256, file);
+        assert_eq!(parse(&body, HashAlgorithm::Sha256).unwrap(), sha256);
+        let body = format!("{}  {}\n", sha256.to_uppercase(), file);
+        assert_eq!(parse(&body, HashAlgorithm::Sha256).unwrap(), sha256);
+        assert_eq!(parse(sha256, HashAlgorithm::Sha256).unwrap(), sha256);
+
+        assert!(parse(&format!("{} *other.tar.gz", sha256), HashAlgorithm::Sha256).is_err());
+        assert!(parse(&format!("{} *{}", sha256, file), HashAlgorithm::Sha512).is_err());
+        assert!(parse("<html>Not Found</html>", HashAlgorithm::Sha256).is_err());
+        assert!(parse("", HashAlgorithm::Sha256).is_err());
+
+        let sha512 = "ab".repeat(64);
+        let body = format!("{} *{}", sha512, file);
+        assert_eq!(parse(&body, HashAlgorithm::Sha512).unwrap(), sha512);
+    }
+
+    #[test]
+    fn test_from_link() {
+        let link = "https://download.jetbrains.com/webstorm/WebStorm-2025.1.tar.gz";
+        assert_eq!(
+            HashAlgorithm::from_link(&format!("{}.sha256", link)),
+            Some(HashAlgorithm::Sha256)
+        );
+        assert_eq!(
+            HashAlgorithm::from_link(&format!("{}.sha512", link)),
+            Some(HashAlgorithm::Sha512)
+        );
+        assert_eq!(HashAlgorithm::from_link(&format!("{}.md5", link)), None);
+    }
+}
@@ -0,0 +1,223 @@
+use crate::date::parse_date;
+use crate::error;
+use crate::resolve::version::Version;
+use crate::resolve::{ProductRelease, compare_release};
+use serde::Deserialize;
+use snafu::whatever;
+use std::cmp::Ordering;
+use std::fmt;
+
+/// Which release of the collection the manifest should be on.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Policy {
+    /// The newest release of the channel.
+    #[default]
+    Latest,
+    /// The newest release of a `majorVersion` line, e.g. `2024.3`.
+    Track(String),
+    /// Exactly this version, or build number.
+    Pin(String),
+}
+
+/// Holds back releases until they are `min_age` days old, a pinned version is never held back.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Cooldown {
+    pub min_age: u32,
+    /// Still list the held back releases in the appdata.
+    pub announce_held: bool,
+}
+
+/// The policy of one product in the config, `pin` and `track` exclude each other.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct PolicyDef {
+    pub pin: Option<String>,
+    pub track: Option<String>,
+    pub min_age: Option<u32>,
+    pub announce_held: bool,
+}
+
+impl PolicyDef {
+    pub fn to_policy(&self) -> error::Result<Policy> {
+        match (&self.pin, &self.track) {
+            (Some(_), Some(_)) => {
+                whatever!("A policy can either pin a version or track a line, not both")
+            }
+            (Some(version), None) => Ok(Policy::Pin(version.clone())),
+            (None, Some(major)) => Ok(Policy::Track(major.clone())),
+            (None, None) => Ok(Policy::Latest),
+        }
+    }
+
+    #[inline]
+    pub fn to_cooldown(&self) -> Cooldown {
+        Cooldown {
+            min_age: self.min_age.unwrap_or_default(),
+            announce_held: self.announce_held,
+        }
+    }
+}
+
+impl Policy {
+    fn matches(&self, release: &ProductRelease) -> bool {
+        match self {
+            Policy::Latest => true,
+            Policy::Track(major) => match release.major_version {
+                Some(major_version) => major_version == major,
+                // Older records may lack `majorVersion`, fall back to the version itself
+                None => in_line(major, release.version),
+            },
+            Policy::Pin(version) => release.version == version || release.build == Some(version),
+        }
+    }
+
+    /// The index of the chosen release in `collection`, which is sorted from newest to oldest.
+    #[inline]
+    pub fn select(&self, collection: &[ProductRelease]) -> Option<usize> {
+        collection.iter().position(|release| self.matches(release))
+    }
+
+    /// Whether moving from the `current` version or build to `target` is an update.
+    ///
+    /// Only a newer release is, unless `current` does not follow the policy, e.g. another
+    /// version than the pinned one. An unknown `current` is always replaced.
+    pub fn accepts(&self, current: Option<&str>, target: &ProductRelease) -> bool {
+        let Some(current) = current else {
+            return true;
+        };
+        let ordering = compare_release(target, current);
+        match self {
+            Policy::Pin(_) => ordering != Some(Ordering::Equal),
+            // A build number does not tell the line, so it is only compared
+            Policy::Track(major)
+                if Version::parse(current).is_some_and(|version| !version.is_build())
+                    && !in_line(major, current) =>
+            {
+                true
+            }
+            _ => ordering.is_none_or(|ordering| ordering == Ordering::Greater),
+        }
+    }
+
+    /// Like [`Policy::select`], but skips the releases younger than `min_age` days on `today`,
+    /// which counts days since the Unix epoch.
+    pub fn select_aged(
+        &self,
+        collection: &[ProductRelease],
+        min_age: u32,
+        today: i64,
+    ) -> Option<usize> {
+        if min_age == 0 || matches!(self, Policy::Pin(_)) {
+            return self.select(collection);
+        }
+        // A date which can not be read is treated as too young, as its age is unknown
+        collection.iter().position(|release| {
+            self.matches(release)
+                && release_age(release, today).is_some_and(|age| age >= min_age as i64)
+        })
+    }
+}
+
+/// Whether `version` is on the `major` line, `2024.3` takes `2024.3.5` but not `2024.30`.
+#[inline]
+fn in_line(major: &str, version: &str) -> bool {
+    version
+        .strip_prefix(major)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('.'))
+}
+
+/// Days from the `YYYY-MM-DD` date of `release` to `today`.
+#[inline]
+pub fn release_age(release: &ProductRelease, today: i64) -> Option<i64> {
+    parse_date(release.date).map(|day| today - day)
+}
+
+impl fmt::Display for Policy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Policy::Latest => f.write_str("latest"),
+            Policy::Track(major) => write!(f, "track {}", major),
+            Policy::Pin(version) => write!(f, "pin {}", version),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn release<'a>(version: &'a str, major_version: Option<&'a str>) -> ProductRelease<'a> {
+        dated(version, major_version, "")
+    }
+
+    fn dated<'a>(
+        version: &'a str,
+        major_version: Option<&'a str>,
+        date: &'a str,
+    ) -> ProductRelease<'a> {
+        let mut release = ProductRelease::stub(version, date);
+        release.major_version = major_version;
+        release.build = Some("243.25659.40").filter(|_| version == "2024.3.5");
+        release
+    }
+
+    #[test]
+    fn test_select() {
+        let collection = [
+            release("2025.1", Some("2025.1")),
+            release("2024.3.5", Some("2024.3")),
+            release("2024.3.4", None),
+            release("2024.30", None),
+        ];
+        let select = |policy: Policy| policy.select(&collection);
+        assert_eq!(select(Policy::Latest), Some(0));
+        assert_eq!(select(Policy::Track("2024.3".to_string())), Some(1));
+        assert_eq!(select(Policy::Track("2024.2".to_string())), None);
+        assert_eq!(select(Policy::Pin("2024.3.4".to_string())), Some(2));
+        assert_eq!(select(Policy::Pin("243.25659.40".to_string())), Some(1));
+        assert_eq!(Policy::Latest.select(&[]), None);
+
+        // Without `majorVersion`, `2024.3` must not match `2024.30`
+        let collection = [release("2024.30", None), release("2024.3.4", None)];
+        assert_eq!(
+            Policy::Track("2024.3".to_string()).select(&collection),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn test_accepts() {
+        let target = release("2024.3.5", Some("2024.3"));
+        let track = Policy::Track("2024.3".to_string());
+        assert!(Policy::Latest.accepts(Some("2024.3.4"), &target));
+        assert!(Policy::Latest.accepts(Some("243.24978.79"), &target));
+        assert!(Policy::Latest.accepts(None, &target));
+        assert!(!Policy::Latest.accepts(Some("2024.3.5.0"), &target));
+        assert!(!Policy::Latest.accepts(Some("2025.1"), &target));
+        assert!(!Policy::Latest.accepts(Some("243.25659.40"), &target));
+        // Going back is fine when the manifest is off the policy
+        assert!(track.accepts(Some("2025.1"), &target));
+        assert!(!track.accepts(Some("2024.3.5"), &target));
+        assert!(Policy::Pin("2024.3.5".to_string()).accepts(Some("2025.1"), &target));
+        assert!(!Policy::Pin("2024.3.5".to_string()).accepts(Some("2024.3.5"), &target));
+    }
+
+    #[test]
+    fn test_select_aged() {
+        let collection = [
+            dated("2025.1.1", Some("2025.1"), "2025-04-30"),
+            dated("2025.1", Some("2025.1"), "2025-04-16"),
+            dated("2024.3.5", Some("2024.3"), "2025-03-13"),
+        ];
+        let today = parse_date("2025-05-02").unwrap();
+        let select = |policy: Policy, min_age| policy.select_aged(&collection, min_age, today);
+        assert_eq!(select(Policy::Latest, 0), Some(0));
+        assert_eq!(select(Policy::Latest, 2), Some(0));
+        assert_eq!(select(Policy::Latest, 7), Some(1));
+        assert_eq!(select(Policy::Latest, 30), Some(2));
+        assert_eq!(select(Policy::Latest, 60), None);
+        assert_eq!(select(Policy::Track("2025.1".to_string()), 30), None);
+        // Pinning is an explicit choice, it is never held back
+        assert_eq!(select(Policy::Pin("2025.1.1".to_string()), 30), Some(0));
+    }
+}
@@ -1,25 +1,56 @@
+use crate::config::ProductDef;
 use crate::error;
+use crate::utils::{ManifestSignals, read_signals};
+use regex::Regex;
 use snafu::{whatever, OptionExt, ResultExt};
+use std::path::{Path, PathBuf};
 
 pub struct ProductInfo {
+    dir: PathBuf,
     paths: Vec<String>,
 
-    short: String,
-    name: String,
-    lowercase: String,
-    code: String,
+    product: ProductDef,
 }
 
-type Pair<'a> = (&'a str, &'a str, &'a str, &'a str);
-
 impl ProductInfo {
 
-    #[inline]
-    pub fn new_with_current_dir() -> error::Result<ProductInfo> {
-        let mut paths_iter = std::fs::read_dir("./")
-            .with_whatever_context(|e| format!("Failed to read directory: {}", e))?
-            .into_iter();
-        let paths = paths_iter.try_fold(Vec::new(), |mut acc, path| {
+    /// Resolves the product from the `app-id` and `x-checker-data` of the manifest, then from
+    /// its `com.jetbrains.<name>` file name, and from the file names in `dir` only without one.
+    pub fn resolve(
+        dir: &Path,
+        manifest: Option<&Path>,
+        products: &[ProductDef],
+    ) -> error::Result<ProductInfo> {
+        let paths = Self::list_dir(dir)?;
+        let manifest = match manifest {
+            Some(manifest) => Some(manifest.to_path_buf()),
+            None => Self::detect_manifest(dir, &paths, products)?,
+        };
+        if let Some(manifest) = manifest {
+            let signals = read_signals(&manifest)?;
+            let product = match Self::from_signals(&signals, products)? {
+                Some(product) => Some(product),
+                None => Self::from_manifest_name(&manifest, products),
+            };
+            if let Some(product) = product {
+                return Ok(ProductInfo {
+                    dir: dir.to_path_buf(),
+                    paths,
+
+                    product: product.clone(),
+                });
+            }
+        }
+        Self::from_lowcase_name(dir.to_path_buf(), paths, products).with_whatever_context(|| {
+            format!("Failed to find any jetbrains files in {}", dir.display())
+        })
+    }
+
+    fn list_dir(dir: &Path) -> error::Result<Vec<String>> {
+        let mut paths_iter = std::fs::read_dir(dir).with_whatever_context(|e| {
+            format!("Failed to read directory {}: {}", dir.display(), e)
+        })?;
+        paths_iter.try_fold(Vec::new(), |mut acc, path| {
             let s = path
                 .with_whatever_context(|x| format!("Failed to read directory \"{}\"", x))
                 .and_then(|dir| {
@@ -32,65 +63,236 @@ impl ProductInfo {
                 })?;
             acc.push(s);
             Ok(acc)
-        })?;
-        Self::from_lowcase_name(paths)
-            .whatever_context("Failed to find any jetbrains files in current directory")
+        })
     }
 
-    fn from_lowcase_name(paths: Vec<String>) -> Option<ProductInfo> {
+    /// Looks for `com.jetbrains.<name>.<ext>` of any registered product in `paths`.
+    fn detect_manifest(
+        dir: &Path,
+        paths: &[String],
+        products: &[ProductDef],
+    ) -> error::Result<Option<PathBuf>> {
+        let found = products
+            .iter()
+            .flat_map(|product| Self::manifest_names(&product.name))
+            .filter(|name| paths.contains(name))
+            .collect::<Vec<String>>();
+        match found.as_slice() {
+            [] => Ok(None),
+            [name] => Ok(Some(dir.join(name))),
+            names => whatever!(
+                "Found several manifests in {}: {}, please choose one with --manifest",
+                dir.display(),
+                names.join(", ")
+            ),
+        }
+    }
 
-        // 用静态数组维护关键词与其他参数的对应关系
-        const PAIRS: [Pair; 5] = [
-            ("clion", "clion", "CLion", "CL"),
-            ("rustrover", "rustrover", "RustRover", "RR"),
-            ("webstorm", "webstorm", "WebStorm", "WS"),
-            ("goland", "goland", "GoLand", "GL"),
-            ("pycharm", "pycharm-community", "PyCharm-Community", "PCC"),
-        ];
+    /// The product whose `com.jetbrains.<name>.<ext>` is the file name of `manifest`.
+    fn from_manifest_name<'a>(
+        manifest: &Path,
+        products: &'a [ProductDef],
+    ) -> Option<&'a ProductDef> {
+        let file_name = manifest.file_name()?.to_str()?;
+        products.iter().find(|product| {
+            Self::manifest_names(&product.name)
+                .iter()
+                .any(|name| name == file_name)
+        })
+    }
+
+    /// Matches the declared identifiers against the registry, `None` if the manifest declares neither.
+    fn from_signals<'a>(
+        signals: &ManifestSignals,
+        products: &'a [ProductDef],
+    ) -> error::Result<Option<&'a ProductDef>> {
+        let code = match signals.codes.as_slice() {
+            [] => None,
+            [code] => Some(code),
+            codes => whatever!(
+                "The manifest declares several x-checker-data codes: {}",
+                codes.join(", ")
+            ),
+        };
+        let app_id = signals.app_id.as_deref();
+        let by_app_id = match app_id.and_then(|id| id.strip_prefix("com.jetbrains.")) {
+            Some(name) => Some(
+                products
+                    .iter()
+                    .find(|p| p.name == name)
+                    .with_whatever_context(|| {
+                        format!(
+                            "Unknown app-id {}, please add it to the config",
+                            app_id.unwrap_or_default()
+                        )
+                    })?,
+            ),
+            None => None,
+        };
+
+        match (by_app_id, code) {
+            (Some(product), Some(code)) if product.code != *code => whatever!(
+                "The app-id {} belongs to {} with code {}, but x-checker-data declares code {}",
+                app_id.unwrap_or_default(),
+                product.display_name,
+                product.code,
+                code
+            ),
+            (Some(product), _) => Ok(Some(product)),
+            (None, Some(code)) => {
+                let product = products
+                    .iter()
+                    .find(|p| p.code == *code)
+                    .with_whatever_context(|| {
+                        format!("Unknown x-checker-data code {}, please add it to the config", code)
+                    })?;
+                if let Some(app_id) = app_id {
+                    whatever!(
+                        "The x-checker-data code {} belongs to {}, but the app-id is {}",
+                        code,
+                        product.display_name,
+                        app_id
+                    );
+                }
+                Ok(Some(product))
+            }
+            (None, None) => Ok(None),
+        }
+    }
 
+    #[inline]
+    fn manifest_names(name: &str) -> [String; 3] {
+        ["yaml", "yml", "json"].map(|ext| format!("com.jetbrains.{}.{}", name, ext))
+    }
+
+    pub(crate) fn from_lowcase_name(
+        dir: PathBuf,
+        paths: Vec<String>,
+        products: &[ProductDef],
+    ) -> Option<ProductInfo> {
         // 迭代查找：一旦找到包含关键词的，就返回对应
-        PAIRS
-            .into_iter()
-            .find(|(_, lc, _, _)| {
-                paths.iter().any(|p| p.to_lowercase().contains(lc))
+        products
+            .iter()
+            .find(|product| {
+                paths.iter().any(|p| p.to_lowercase().contains(&product.lowercase))
             })
-            .map(|(short, lc, name, code)| ProductInfo {
+            .map(|product| ProductInfo {
+                dir,
                 paths,
 
-                short: short.to_string(),
-                name: name.to_string(),
-                lowercase: lc.to_string(),
-                code: code.to_string(),
+                product: product.clone(),
             })
     }
-    
+
     #[inline]
-    pub fn find_yaml_from_path(&self) -> Option<String> {
-        let possible_paths = [format!("com.jetbrains.{}.yaml", self.name), format!("com.jetbrains.{}.yml", self.name)];
+    pub fn find_manifest_from_path(&self) -> Option<PathBuf> {
+        let possible_paths = Self::manifest_names(&self.product.name);
         possible_paths.iter().find_map(|path| {
             self.paths.iter().any(|s| {
                 s.eq(path)
-            }).then(|| path.to_string())
+            }).then(|| self.dir.join(path))
         })
     }
 
+    #[inline]
+    pub fn appdata_path(&self) -> PathBuf {
+        self.dir.join(format!("com.jetbrains.{}.appdata.xml", self.product.name))
+    }
+
+    /// Extracts the version from a download link, using the `archive` naming of the product.
+    pub fn version_from_link(&self, link: &str) -> Option<String> {
+        let file_name = link.rsplit('/').next()?;
+        let pattern = regex::escape(&self.product.archive)
+            .replace(r"\{version\}", "(.+?)")
+            .replace(r"\{arch\}", "(?:-aarch64)?");
+        Regex::new(&format!("^{}$", pattern))
+            .ok()?
+            .captures(file_name)
+            .map(|c| c[1].to_string())
+    }
+
     #[inline]
     pub fn name(&self) -> &str {
-        &self.name
+        &self.product.name
     }
 
     #[inline]
-    pub fn lowercase(&self) -> &str {
-        &self.lowercase
+    pub fn display_name(&self) -> &str {
+        &self.product.display_name
     }
 
     #[inline]
     pub fn code(&self) -> &str {
-        &self.code
+        &self.product.code
     }
 
     #[inline]
     pub fn short(&self) -> &str {
-        self.short.as_str()
+        self.product.short.as_str()
+    }
+
+    #[inline]
+    pub fn tarball(&self) -> &str {
+        &self.product.tarball
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    fn product(file: &str) -> ProductInfo {
+        let config = Config::builtin().unwrap();
+        ProductInfo::from_lowcase_name(PathBuf::from("."), vec![file.to_string()], &config.products)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_version_from_link() {
+        let webstorm = product("com.jetbrains.WebStorm.yaml");
+        assert_eq!(
+            webstorm.version_from_link("https://download.jetbrains.com/webstorm/WebStorm-2024.3.5-aarch64.tar.gz"),
+            Some("2024.3.5".to_string())
+        );
+        let pycharm = product("com.jetbrains.PyCharm-Community.yaml");
+        assert_eq!(
+            pycharm.version_from_link("https://download.jetbrains.com/python/pycharm-community-2025.1.tar.gz"),
+            Some("2025.1".to_string())
+        );
+        assert_eq!(webstorm.version_from_link("https://example.com/other.zip"), None);
+    }
+
+    #[test]
+    fn test_from_signals() {
+        let config = Config::builtin().unwrap();
+        let signals = |app_id: Option<&str>, codes: &[&str]| ManifestSignals {
+            app_id: app_id.map(str::to_owned),
+            codes: codes.iter().map(|c| c.to_string()).collect(),
+        };
+        let resolve = |signals: ManifestSignals| {
+            ProductInfo::from_signals(&signals, &config.products).map(|p| p.map(|p| p.short.clone()))
+        };
+
+        let webstorm = Some("webstorm".to_string());
+        assert_eq!(resolve(signals(Some("com.jetbrains.WebStorm"), &["WS"])).unwrap(), webstorm);
+        assert_eq!(resolve(signals(Some("com.jetbrains.WebStorm"), &[])).unwrap(), webstorm);
+        assert_eq!(resolve(signals(None, &["WS"])).unwrap(), webstorm);
+        assert_eq!(resolve(signals(None, &[])).unwrap(), None);
+        assert!(resolve(signals(Some("com.jetbrains.WebStorm"), &["CL"])).is_err());
+        assert!(resolve(signals(Some("org.example.Foo"), &["WS"])).is_err());
+        assert!(resolve(signals(None, &["WS", "CL"])).is_err());
+        // A JetBrains app-id which is not registered must not fall back to another product
+        assert!(resolve(signals(Some("com.jetbrains.Aqua"), &[])).is_err());
+
+        // Without either, the manifest name decides, other files mentioning a product do not
+        let dir = std::env::temp_dir().join(format!("fju-signals-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("com.jetbrains.WebStorm.yaml"), "modules: []\n").unwrap();
+        std::fs::write(dir.join("clion-notes.txt"), "").unwrap();
+        let product = ProductInfo::resolve(&dir, None, &config.products).unwrap();
+        assert_eq!(product.short(), "webstorm");
+        assert!(product.find_manifest_from_path().is_some());
+        std::fs::remove_dir_all(dir).unwrap();
     }
 }
@@ -1,21 +1,24 @@
-use regex::Regex;
-use reqwest::Client;
-use std::sync::Arc;
+use crate::error;
+use crate::error::Error;
+use crate::http::Http;
+use crate::resolve::{HashAlgorithm, parse_sidecar};
+use serde::Deserialize;
+use std::fmt;
 
 #[derive(Debug, Clone)]
 pub enum Checksum<'a> {
     Link(&'a str),
-    TypeAndRes(String, String),
+    TypeAndRes(HashAlgorithm, String),
 }
 
 impl<'a> Checksum<'a> {
     #[inline]
-    pub fn from_str(s: &str) -> Checksum {
+    pub fn from_str(s: &str) -> Checksum<'_> {
         Checksum::Link(s)
     }
 
     #[inline]
-    pub fn into_type_and_res(self) -> (String, String) {
+    pub fn into_type_and_res(self) -> (HashAlgorithm, String) {
         if let Checksum::TypeAndRes(s1, s2) = self {
             (s1, s2)
         } else {
@@ -24,98 +27,255 @@ impl<'a> Checksum<'a> {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Platform<'a> {
     pub link: &'a str,
     pub size: usize,
     pub checksum_link: Option<Checksum<'a>>,
 }
 
-#[derive(Clone, Debug)]
-pub struct RE {
-    re: Arc<Regex>,
+/// A Flatpak arch with a Linux download in the JetBrains API.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arch {
+    X86_64,
+    Aarch64,
+}
+
+impl Arch {
+    pub const ALL: [Arch; 2] = [Arch::X86_64, Arch::Aarch64];
+
+    /// The arch in `only-arches` of a Flatpak source.
+    #[inline]
+    pub fn flatpak(self) -> &'static str {
+        match self {
+            Arch::X86_64 => "x86_64",
+            Arch::Aarch64 => "aarch64",
+        }
+    }
+
+    /// The key of the download in the `downloads` of a release record.
+    #[inline]
+    pub fn api_key(self) -> &'static str {
+        match self {
+            Arch::X86_64 => "linux",
+            Arch::Aarch64 => "linuxARM64",
+        }
+    }
+
+    #[inline]
+    pub fn from_flatpak(arch: &str) -> Option<Arch> {
+        Arch::ALL.into_iter().find(|a| a.flatpak() == arch)
+    }
+}
+
+impl fmt::Display for Arch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.flatpak())
+    }
 }
 
-impl Default for RE {
+/// The `type` of a release in the JetBrains API.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReleaseType {
+    Release,
+    Rc,
+    Eap,
+}
+
+impl ReleaseType {
     #[inline]
-    fn default() -> RE {
-        RE {
-            re: Arc::new(Regex::new("^[A-Za-z0-9]+").unwrap()),
+    pub fn as_api(self) -> &'static str {
+        match self {
+            ReleaseType::Release => "release",
+            ReleaseType::Rc => "rc",
+            ReleaseType::Eap => "eap",
         }
     }
+
+    #[inline]
+    pub fn is_stable(self) -> bool {
+        self == ReleaseType::Release
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ProductRelease<'a> {
     pub date: &'a str,
     pub version: &'a str,
-    pub linux_amd64: Platform<'a>,
-    pub linux_arm64: Option<Platform<'a>>,
-    pub re: RE,
+    pub major_version: Option<&'a str>,
+    pub build: Option<&'a str>,
+    pub notes_link: Option<&'a str>,
+    pub release_type: ReleaseType,
+    /// The Linux downloads of the release, at most one per arch.
+    pub platforms: Vec<(Arch, Platform<'a>)>,
+}
+
+impl<'a> Platform<'a> {
+    /// Downloads the sidecar at `url` and checks that it describes the tarball of this platform.
+    async fn fetch_checksum(
+        &self,
+        http: &Http,
+        url: &str,
+    ) -> error::Result<(HashAlgorithm, String)> {
+        let algorithm = HashAlgorithm::from_link(url).ok_or_else(|| Error::ChecksumFormat {
+            url: url.to_string(),
+            reason: "unsupported checksum type, expected .sha256 or .sha512".to_string(),
+        })?;
+        let file_name = self.link.rsplit('/').next().unwrap_or(self.link);
+
+        let body = http.get_text(url).await?;
+        parse_sidecar(&body, algorithm, file_name)
+            .map(|hash| (algorithm, hash))
+            .map_err(|reason| Error::ChecksumFormat {
+                url: url.to_string(),
+                reason,
+            })
+    }
+
+    async fn complete_checksum(&mut self, http: &Http) -> error::Result<()> {
+        if let Some(Checksum::Link(link)) = self.checksum_link {
+            let (algorithm, hash) = self.fetch_checksum(http, link).await?;
+            self.checksum_link = Some(Checksum::TypeAndRes(algorithm, hash));
+        }
+        Ok(())
+    }
 }
 
 impl<'a> ProductRelease<'a> {
-    pub async fn complete_checksum(&mut self, client: Client) {
-        let get_checksum = async |url: &str| -> Option<String> {
-            for i in 0..3 {
-                if i > 0 {
-                    tokio::time::sleep(std::time::Duration::from_secs(i)).await;
-                }
-                let result = client.get(url).send().await;
-                match result {
-                    Ok(res) => {
-                        let text = res
-                            .text()
-                            .await
-                            .ok()
-                            .map(|t| self.re.re.find(t.as_str()).unwrap().as_str().to_owned());
-                        return text;
-                    }
-                    Err(e) => {
-                        println!("Failed to get release checksum: {}", e);
-                    }
-                }
-            }
-            None
-        };
-
-        if let Some(Checksum::Link(link)) = &self.linux_amd64.checksum_link {
-            let tp = link.rsplit('.').next().unwrap().to_string();
-            self.linux_amd64.checksum_link =
-                Some(Checksum::TypeAndRes(tp, get_checksum(link).await.unwrap()));
+    /// The download of `arch`, `None` if the release has none.
+    #[inline]
+    pub fn platform(&self, arch: Arch) -> Option<&Platform<'a>> {
+        self.platforms
+            .iter()
+            .find(|(a, _)| *a == arch)
+            .map(|(_, platform)| platform)
+    }
+
+    /// Replaces the checksum links of the Linux platforms with the checksums they point to.
+    pub async fn complete_checksum(&mut self, http: &Http) -> error::Result<()> {
+        for (_, platform) in &mut self.platforms {
+            platform.complete_checksum(http).await?;
         }
-        if let Some(checksum) = &mut self.linux_arm64 {
-            if let Some(Checksum::Link(link)) = checksum.checksum_link {
-                let tp = link.rsplit('.').next().unwrap().to_string();
-                checksum.checksum_link =
-                    Some(Checksum::TypeAndRes(tp, get_checksum(link).await.unwrap()));
-            }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+impl<'a> Platform<'a> {
+    /// A download whose sha256 is already resolved.
+    pub fn stub(link: &'a str, size: usize, sha256: &str) -> Platform<'a> {
+        Platform {
+            link,
+            size,
+            checksum_link: Some(Checksum::TypeAndRes(
+                HashAlgorithm::Sha256,
+                sha256.to_string(),
+            )),
         }
     }
 }
 
+#[cfg(test)]
+impl<'a> ProductRelease<'a> {
+    /// A stable release with an empty x86_64 download and nothing else, tests set the fields
+    /// and platforms they need.
+    pub fn stub(version: &'a str, date: &'a str) -> ProductRelease<'a> {
+        ProductRelease {
+            date,
+            version,
+            major_version: None,
+            build: None,
+            notes_link: None,
+            release_type: ReleaseType::Release,
+            platforms: vec![(
+                Arch::X86_64,
+                Platform {
+                    link: "",
+                    size: 0,
+                    checksum_link: None,
+                },
+            )],
+        }
+    }
+
+    /// Replaces the download of `arch`, `None` removes it.
+    pub fn set_platform(&mut self, arch: Arch, platform: Option<Platform<'a>>) {
+        self.platforms.retain(|(a, _)| *a != arch);
+        if let Some(platform) = platform {
+            self.platforms.push((arch, platform));
+            self.platforms
+                .sort_by_key(|(a, _)| Arch::ALL.iter().position(|all| all == a));
+        }
+    }
+
+    #[inline]
+    pub fn platform_mut(&mut self, arch: Arch) -> Option<&mut Platform<'a>> {
+        self.platforms
+            .iter_mut()
+            .find(|(a, _)| *a == arch)
+            .map(|(_, platform)| platform)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::http::mock::MockServer;
+
+    const SHA256: &str = "f181eb1348c04a60f860fd9ea9616261c6c012a7916e76380652d129e87503ea";
+
+    fn release_with(checksum: &str) -> ProductRelease<'_> {
+        let mut release = ProductRelease::stub("2025.1", "2025-04-16");
+        release.set_platform(
+            Arch::X86_64,
+            Some(Platform {
+                link: "https://download.jetbrains.com/webstorm/WebStorm-2025.1.tar.gz",
+                size: 0,
+                checksum_link: Some(Checksum::Link(checksum)),
+            }),
+        );
+        release
+    }
+
     #[tokio::test]
     async fn test_complete_checksum() {
-        let client = Client::default();
-        let checksum =
-            Checksum::Link("https://download.jetbrains.com/webstorm/WebStorm-2025.1.tar.gz.sha256");
-        let size = 0;
-        let link = Default::default();
-        let mut relase = ProductRelease {
-            date: "",
-            version: "",
-            linux_amd64: Platform {
-                link,
-                size,
-                checksum_link: Some(checksum),
-            },
-            linux_arm64: None,
-            re: RE::default(),
-        };
-        relase.complete_checksum(client).await;
-        println!("release: {:?}", relase);
+        let sidecar = format!("{} *WebStorm-2025.1.tar.gz\n", SHA256);
+        let server = MockServer::start(&[
+            (
+                "/webstorm/WebStorm-2025.1.tar.gz.sha256",
+                &[(500, ""), (200, &sidecar)],
+            ),
+            (
+                "/webstorm/error-page.sha256",
+                &[(200, "<html>Not Found</html>")],
+            ),
+        ])
+        .await;
+        let http = server.http();
+
+        let mut release =
+            release_with("https://download.jetbrains.com/webstorm/WebStorm-2025.1.tar.gz.sha256");
+        release.complete_checksum(&http).await.unwrap();
+        let (algorithm, hash) = release
+            .platform(Arch::X86_64)
+            .and_then(|platform| platform.checksum_link.clone())
+            .unwrap()
+            .into_type_and_res();
+        assert_eq!((algorithm, hash.as_str()), (HashAlgorithm::Sha256, SHA256));
+
+        let mut release = release_with("https://download.jetbrains.com/webstorm/error-page.sha256");
+        assert!(matches!(
+            release.complete_checksum(&http).await,
+            Err(Error::ChecksumFormat { .. })
+        ));
+        let mut release = release_with("https://download.jetbrains.com/webstorm/missing.sha256");
+        assert!(matches!(
+            release.complete_checksum(&http).await,
+            Err(Error::Http {
+                status: Some(404),
+                ..
+            })
+        ));
     }
 }
@@ -0,0 +1,95 @@
+use crate::resolve::ProductRelease;
+use std::cmp::Ordering;
+
+/// A dotted JetBrains version or build number, e.g. `2024.3.2.1` or `243.24978.79`.
+///
+/// Trailing zero components do not count, `2019.2.0` equals `2019.2` and `2024.2.0.1` is newer
+/// than both `2024.2` and `2024.2.0`.
+#[derive(Debug, Clone)]
+pub struct Version {
+    parts: Vec<u64>,
+}
+
+impl Version {
+    pub fn parse(version: &str) -> Option<Version> {
+        let parts = version
+            .trim()
+            .split('.')
+            .map(|part| part.parse().ok())
+            .collect::<Option<Vec<u64>>>()?;
+        Some(Version { parts })
+    }
+
+    /// Build numbers start with the three digit branch, e.g. `243`, versions with the year.
+    #[inline]
+    pub fn is_build(&self) -> bool {
+        self.parts.first().is_some_and(|&first| first < 1000)
+    }
+
+    #[inline]
+    fn significant(&self) -> &[u64] {
+        let len = self
+            .parts
+            .iter()
+            .rposition(|&part| part != 0)
+            .map_or(0, |last| last + 1);
+        &self.parts[..len]
+    }
+}
+
+impl PartialEq for Version {
+    fn eq(&self, other: &Self) -> bool {
+        self.significant() == other.significant()
+    }
+}
+
+impl Eq for Version {}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.significant().cmp(other.significant())
+    }
+}
+
+/// Compares `release` with a version or build number read from a file, e.g. a download link.
+///
+/// `None` if `current` can not be read, or is a build number and `release` has none.
+pub fn compare_release(release: &ProductRelease, current: &str) -> Option<Ordering> {
+    let current = Version::parse(current)?;
+    let release = if current.is_build() {
+        Version::parse(release.build?)?
+    } else {
+        Version::parse(release.version)?
+    };
+    Some(release.cmp(&current))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(version: &str) -> Version {
+        Version::parse(version).unwrap()
+    }
+
+    #[test]
+    fn test_version_order() {
+        assert_eq!(version("2019.2.0"), version("2019.2"));
+        assert!(version("2024.2.0.1") > version("2024.2"));
+        assert!(version("2024.2.0.1") < version("2024.2.1"));
+        assert!(version("2024.3.2.1") > version("2024.3.2"));
+        assert!(version("2024.3.10") > version("2024.3.9"));
+        assert!(version("2025.1") > version("2024.3.5"));
+        assert!(version("243.24978.79") < version("243.25659.40"));
+        assert!(version("243.24978.79").is_build());
+        assert!(!version("2024.3").is_build());
+        assert!(Version::parse("2025.2 EAP").is_none());
+        assert!(Version::parse("").is_none());
+    }
+}
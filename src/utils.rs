@@ -1,16 +1,14 @@
+mod file;
 mod json;
+mod pr;
+mod splice;
+mod verify;
 mod xml;
 mod yaml;
 
-pub use json::read_json;
-pub use xml::update_xml;
-pub use yaml::update_yaml;
-
-#[macro_export]
-macro_rules! get_with_check {
-    ($item:ident,$target:ident,$supplement:literal) => {
-        $item.get(stringify!($target)).with_whatever_context(|| {
-            format!("Failed to find {} {}", stringify!($target), $supplement)
-        })?
-    };
-}
+pub use file::{Backup, FileChange, write_atomic};
+pub use json::{fetch_json, read_json};
+pub use pr::render_pr_body;
+pub use verify::verify_release;
+pub use xml::{release_versions, render_xml, verify_appdata};
+pub use yaml::{ManifestSignals, current_links, read_signals, render_yaml, verify_manifest};
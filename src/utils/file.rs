@@ -0,0 +1,377 @@
+use crate::date::timestamp;
+use crate::error;
+use quick_xml::Reader;
+use quick_xml::events::Event;
+use similar::TextDiff;
+use snafu::{ResultExt, whatever};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// The original and the rendered content of a file, kept in memory until it is written.
+#[derive(Debug)]
+pub struct FileChange {
+    path: PathBuf,
+    original: String,
+    updated: String,
+}
+
+impl FileChange {
+    #[inline]
+    pub fn new(path: &Path, original: String, updated: String) -> FileChange {
+        FileChange {
+            path: path.to_path_buf(),
+            original,
+            updated,
+        }
+    }
+
+    #[inline]
+    pub fn unchanged(path: &Path, original: String) -> FileChange {
+        let updated = original.clone();
+        Self::new(path, original, updated)
+    }
+
+    /// Reads a file which is kept as it is.
+    pub fn keep(path: &Path) -> error::Result<FileChange> {
+        let original = std::fs::read_to_string(path).with_whatever_context(|e| {
+            format!(r#"Failed to read "{}", source: {}"#, path.display(), e)
+        })?;
+        Ok(Self::unchanged(path, original))
+    }
+
+    #[inline]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    #[inline]
+    pub fn is_changed(&self) -> bool {
+        self.original != self.updated
+    }
+
+    pub fn unified_diff(&self) -> String {
+        let path = self.path.display().to_string();
+        TextDiff::from_lines(&self.original, &self.updated)
+            .unified_diff()
+            .context_radius(3)
+            .header(&format!("a/{}", path), &format!("b/{}", path))
+            .to_string()
+    }
+
+    /// Checks that the rendered content still parses, by the extension of the file.
+    pub fn validate(&self) -> error::Result<()> {
+        let path = self.path.display();
+        match self.path.extension().and_then(|e| e.to_str()) {
+            Some("json") => {
+                serde_json::from_str::<serde_json::Value>(&self.updated).with_whatever_context(
+                    |e| format!(r#"Rendered "{}" is no valid JSON, source: {}"#, path, e),
+                )?;
+            }
+            Some("yaml" | "yml") => {
+                serde_yaml::from_str::<serde_yaml::Value>(&self.updated).with_whatever_context(
+                    |e| format!(r#"Rendered "{}" is no valid YAML, source: {}"#, path, e),
+                )?;
+            }
+            Some("xml") => {
+                let mut reader = Reader::from_str(&self.updated);
+                loop {
+                    match reader.read_event() {
+                        Ok(Event::Eof) => break,
+                        Ok(_) => {}
+                        Err(e) => whatever!(
+                            r#"Rendered "{}" is no valid XML at position {}, source: {:?}"#,
+                            path,
+                            reader.error_position(),
+                            e
+                        ),
+                    }
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// The current content of `path` as the original, and its `backup` as the update.
+    pub fn from_backup(path: &Path, backup: &Backup) -> error::Result<FileChange> {
+        let read = |path: &Path| {
+            std::fs::read_to_string(path).with_whatever_context(|e| {
+                format!(r#"Failed to read "{}", source: {}"#, path.display(), e)
+            })
+        };
+        Ok(Self::new(path, read(path)?, read(&backup.path)?))
+    }
+
+    /// Writes every changed file or none of them, with `backup` the originals are kept as
+    /// `<name>.<timestamp>.bak` next to them.
+    ///
+    /// The contents are validated and staged in temporary files first, then renamed over the
+    /// files. If a rename fails, the files already replaced get their original content back.
+    pub fn write_all(changes: &[FileChange], backup: bool) -> error::Result<()> {
+        let changed = changes
+            .iter()
+            .filter(|change| change.is_changed())
+            .collect::<Vec<&FileChange>>();
+        for change in changed.iter() {
+            change.validate()?;
+        }
+
+        let mut staged = Vec::with_capacity(changed.len());
+        for change in changed.iter() {
+            let staging = staging_path(&change.path);
+            if let Err(e) = write_synced(&staging, &change.updated)
+                .and_then(|()| copy_permissions(&change.path, &staging))
+            {
+                remove_files(staged.iter().chain([&staging]));
+                return Err(e);
+            }
+            staged.push(staging);
+        }
+        if backup {
+            let stamp = timestamp();
+            for change in changed.iter() {
+                if let Err(e) = write_atomic(&backup_path(&change.path, &stamp), &change.original) {
+                    remove_files(staged.iter());
+                    return Err(e);
+                }
+            }
+        }
+
+        for (pos, (change, staging)) in changed.iter().zip(staged.iter()).enumerate() {
+            if let Err(e) = std::fs::rename(staging, &change.path) {
+                remove_files(staged[pos..].iter());
+                // Every replaced file is restored, the first failure is reported
+                let mut restored = Ok(());
+                for change in changed[..pos].iter() {
+                    if let Err(e) = write_atomic(&change.path, &change.original)
+                        && restored.is_ok()
+                    {
+                        restored = Err(e);
+                    }
+                }
+                whatever!(
+                    r#"Failed to replace "{}", source: {:?}, {}"#,
+                    change.path.display(),
+                    e,
+                    match restored {
+                        Ok(()) => "no file has been changed".to_string(),
+                        Err(restore) => format!("and failed to restore the others: {}", restore),
+                    }
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Puts the original content of every changed file back after [`FileChange::write_all`].
+    pub fn revert_all(changes: &[FileChange]) -> error::Result<()> {
+        for change in changes.iter().filter(|change| change.is_changed()) {
+            write_atomic(&change.path, &change.original)?;
+        }
+        Ok(())
+    }
+}
+
+/// A copy of a file taken by [`FileChange::write_all`] before it was replaced.
+#[derive(Debug)]
+pub struct Backup {
+    pub stamp: String,
+    pub path: PathBuf,
+}
+
+impl Backup {
+    /// The newest backup of `path`, `None` if there is none.
+    pub fn latest(path: &Path) -> error::Result<Option<Backup>> {
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let name = file_name(path);
+        let entries = match std::fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => whatever!(
+                r#"Failed to read the directory "{}", source: {:?}"#,
+                dir.display(),
+                e
+            ),
+        };
+        Ok(entries
+            .filter_map(Result::ok)
+            .filter_map(|entry| {
+                let stamp = entry
+                    .file_name()
+                    .to_str()?
+                    .strip_prefix(&name)?
+                    .strip_prefix('.')?
+                    .strip_suffix(".bak")?
+                    .to_string();
+                // The timestamps sort in time order, anything else is not ours
+                (stamp.len() == 16 && !stamp.contains('.')).then(|| Backup {
+                    stamp,
+                    path: entry.path(),
+                })
+            })
+            .max_by(|a, b| a.stamp.cmp(&b.stamp)))
+    }
+}
+
+#[inline]
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// Next to the file, so that renaming it over the file stays on one filesystem.
+#[inline]
+fn staging_path(path: &Path) -> PathBuf {
+    path.with_file_name(format!(".{}.{}.tmp", file_name(path), std::process::id()))
+}
+
+#[inline]
+fn backup_path(path: &Path, stamp: &str) -> PathBuf {
+    path.with_file_name(format!("{}.{}.bak", file_name(path), stamp))
+}
+
+/// Gives the staged file the mode of the file it replaces, a new file keeps the default one.
+fn copy_permissions(path: &Path, staging: &Path) -> error::Result<()> {
+    let permissions = match std::fs::metadata(path) {
+        Ok(metadata) => metadata.permissions(),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => whatever!(
+            r#"Failed to read the permissions of "{}", source: {:?}"#,
+            path.display(),
+            e
+        ),
+    };
+    std::fs::set_permissions(staging, permissions).with_whatever_context(|e| {
+        format!(
+            r#"Failed to set the permissions of "{}", source: {:?}"#,
+            staging.display(),
+            e
+        )
+    })
+}
+
+/// Writes `content` and flushes it to the disk, so that a rename never exposes a partial file.
+fn write_synced(path: &Path, content: &str) -> error::Result<()> {
+    let write = || -> std::io::Result<()> {
+        let mut file = std::fs::File::create(path)?;
+        file.write_all(content.as_bytes())?;
+        file.sync_all()
+    };
+    write().with_whatever_context(|e| {
+        format!(r#"Failed to write "{}", source: {:?}"#, path.display(), e)
+    })
+}
+
+/// Replaces `path` by a temporary file next to it, readers see either the old or the new content,
+/// the mode of `path` is kept.
+pub fn write_atomic(path: &Path, content: &str) -> error::Result<()> {
+    let staging = staging_path(path);
+    write_synced(&staging, content)?;
+    if let Err(e) = copy_permissions(path, &staging) {
+        let _ = std::fs::remove_file(&staging);
+        return Err(e);
+    }
+    if let Err(e) = std::fs::rename(&staging, path) {
+        let _ = std::fs::remove_file(&staging);
+        whatever!(r#"Failed to replace "{}", source: {:?}"#, path.display(), e);
+    }
+    Ok(())
+}
+
+#[inline]
+fn remove_files<'a>(paths: impl Iterator<Item = &'a PathBuf>) {
+    for path in paths {
+        let _ = std::fs::remove_file(path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_all_changes_nothing_on_failure() {
+        let dir = std::env::temp_dir().join(format!("fju-write-all-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("blocked/inner")).unwrap();
+        let (manifest, appdata) = (dir.join("app.yaml"), dir.join("blocked"));
+        std::fs::write(&manifest, "a: 1\n").unwrap();
+
+        // A directory can not be replaced by a file, so the second rename fails
+        let changes = [
+            FileChange::new(&manifest, "a: 1\n".to_string(), "a: 2\n".to_string()),
+            FileChange::new(&appdata, String::new(), "<a/>".to_string()),
+        ];
+        assert!(FileChange::write_all(&changes, false).is_err());
+        assert_eq!(std::fs::read_to_string(&manifest).unwrap(), "a: 1\n");
+        assert!(appdata.join("inner").is_dir());
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 2);
+
+        let invalid = [FileChange::new(
+            &manifest,
+            "a: 1\n".to_string(),
+            "a: [".to_string(),
+        )];
+        assert!(FileChange::write_all(&invalid, false).is_err());
+        assert_eq!(std::fs::read_to_string(&manifest).unwrap(), "a: 1\n");
+
+        let changes = [FileChange::new(
+            &manifest,
+            "a: 1\n".to_string(),
+            "a: 2\n".to_string(),
+        )];
+        FileChange::write_all(&changes, false).unwrap();
+        assert_eq!(std::fs::read_to_string(&manifest).unwrap(), "a: 2\n");
+        FileChange::revert_all(&changes).unwrap();
+        assert_eq!(std::fs::read_to_string(&manifest).unwrap(), "a: 1\n");
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_unified_diff() {
+        let change = FileChange::new(
+            Path::new("app.yaml"),
+            "a: 1\nb: 2\n".to_string(),
+            "a: 1\nb: 3\n".to_string(),
+        );
+        assert_eq!(
+            change.unified_diff(),
+            "--- a/app.yaml\n+++ b/app.yaml\n@@ -1,2 +1,2 @@\n a: 1\n-b: 2\n+b: 3\n"
+        );
+        assert!(
+            FileChange::unchanged(Path::new("app.yaml"), "a: 1\n".to_string())
+                .unified_diff()
+                .is_empty()
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_writes_keep_the_mode() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("fju-write-mode-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (manifest, appdata) = (dir.join("app.yaml"), dir.join("app.xml"));
+        for (path, mode) in [(&manifest, 0o755), (&appdata, 0o664)] {
+            std::fs::write(path, "").unwrap();
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode)).unwrap();
+        }
+        let mode = |path: &Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+
+        let changes = [
+            FileChange::new(&manifest, String::new(), "a: 1\n".to_string()),
+            FileChange::new(&appdata, String::new(), "<a/>".to_string()),
+        ];
+        FileChange::write_all(&changes, false).unwrap();
+        assert_eq!((mode(&manifest), mode(&appdata)), (0o755, 0o664));
+        FileChange::revert_all(&changes).unwrap();
+        assert_eq!((mode(&manifest), mode(&appdata)), (0o755, 0o664));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
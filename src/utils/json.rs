@@ -1,76 +1,125 @@
-use crate::resolve::{Platform, ProductRelease};
-use crate::{error, resolve};
-use serde_json::Value;
-use snafu::OptionExt;
+use crate::cli::Channel;
+use crate::error;
+use crate::http::Http;
+use crate::resolve::{
+    Arch, Checksum, Download, Platform, ProductInfo, ProductRelease, ReleaseRecord,
+    ReleasesResponse,
+};
+use snafu::{OptionExt, ResultExt};
 
-macro_rules! json_get_with_check {
-    ($item:ident,$target:ident) => {
-        crate::get_with_check!(
-            $item,
-            $target,
-            "in json, which has been downloaded from jetbrains server"
+pub async fn fetch_json(
+    http: &Http,
+    product_info: &ProductInfo,
+    channel: Channel,
+) -> error::Result<Vec<ReleaseRecord>> {
+    let types = channel
+        .release_types()
+        .iter()
+        .map(|t| t.as_api())
+        .collect::<Vec<&str>>()
+        .join(",");
+    let url = Http::api_url(&format!(
+        "/products/releases?code={}&type={}",
+        product_info.code(),
+        types
+    ));
+    let json = http.get_text(&url).await?;
+
+    parse_releases(&json, product_info.code())
+}
+
+/// Picks the records of `code` out of a `products/releases` response body.
+pub fn parse_releases(json: &str, code: &str) -> error::Result<Vec<ReleaseRecord>> {
+    let mut response: ReleasesResponse = serde_json::from_str(json).with_whatever_context(|e| {
+        format!(
+            "Failed to parse response body from jetbrains server, source: {}",
+            e
         )
-    };
+    })?;
+    response
+        .remove(code)
+        .with_whatever_context(|| format!(r#"Failed to find "{}" in JSON top"#, code))
 }
 
-pub fn read_json(array: &Vec<Value>) -> error::Result<Vec<ProductRelease>> {
-    let re = resolve::RE::default();
-    array
-        .iter()
-        .try_fold(Vec::with_capacity(array.len()), |mut acc, x| {
-            fn init_platform(
-                map: &serde_json::Map<String, Value>,
-                is_first: bool,
-            ) -> error::Result<Platform> {
-                let checksum_link = if is_first {
-                    Some(resolve::Checksum::from_str(
-                        json_get_with_check!(map, checksumLink)
-                            .as_str()
-                            .whatever_context(
-                                "Unexpected JSON structure while reading checksumLink",
-                            )?,
-                    ))
-                } else {
-                    None
-                };
-                Ok(Platform {
-                    link: json_get_with_check!(map, link)
-                        .as_str()
-                        .whatever_context("Unexpected JSON structure while reading link")?,
-                    size: json_get_with_check!(map, size)
-                        .as_u64()
-                        .whatever_context("Unexpected JSON structure while reading size")?
-                        as usize,
-                    checksum_link,
-                })
-            }
-            let download = &x["downloads"];
-            if let Some(map) = download["linux"].as_object() {
-                let is_first = acc.len() == 0;
-                let linux_amd64 = init_platform(map, is_first)?;
-                let linux_arm64 = {
-                    if !is_first {
-                        None
-                    } else if let Some(map) = json_get_with_check!(download, linuxARM64).as_object()
-                    {
-                        Some(init_platform(map, is_first)?)
-                    } else {
-                        None
-                    }
-                };
-                let release = ProductRelease {
-                    date: json_get_with_check!(x, date)
-                        .as_str()
-                        .whatever_context("Failed to convert date to string")?,
-                    version: json_get_with_check!(x, version)
-                        .as_str()
-                        .whatever_context("Failed to convert version to string")?,
-                    linux_amd64,
-                    linux_arm64,
-                    re: re.clone(),
-                };
-                acc.push(release);
+pub fn read_json(records: &[ReleaseRecord]) -> error::Result<Vec<ProductRelease<'_>>> {
+    // Several release types are returned type by type, so order them by date first
+    let mut records = records.iter().collect::<Vec<&ReleaseRecord>>();
+    records.sort_by(|a, b| b.date.cmp(&a.date));
+
+    // Every release is complete, so that any of them can be chosen by the update policy
+    fn init_platform(download: &Download) -> Platform<'_> {
+        Platform {
+            link: &download.link,
+            size: download.size as usize,
+            checksum_link: download.checksum_link.as_deref().map(Checksum::from_str),
+        }
+    }
+
+    Ok(records
+        .into_iter()
+        .filter_map(|record| {
+            // Each arch is updated on its own, a release is skipped only without any Linux download
+            let platforms = Arch::ALL
+                .into_iter()
+                .filter_map(|arch| Some((arch, init_platform(record.downloads.linux_of(arch)?))))
+                .collect::<Vec<_>>();
+            if platforms.is_empty() {
+                return None;
             }
-            Ok(acc)
+            Some(ProductRelease {
+                date: &record.date,
+                version: &record.version,
+                major_version: record.major_version.as_deref(),
+                build: record.build.as_deref(),
+                notes_link: record.notes_link.as_deref(),
+                release_type: record.release_type,
+                platforms,
+            })
         })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_json() {
+        let body = include_str!("../../tests/fixtures/releases_WS.json");
+        let records = parse_releases(body, "WS").unwrap();
+        let collection = read_json(&records).unwrap();
+        let versions = collection.iter().map(|r| r.version).collect::<Vec<&str>>();
+        assert_eq!(versions, ["2025.1", "2024.3.5", "2024.3.4"]);
+        assert_eq!(collection[0].build, Some("251.23774.424"));
+        assert!(collection[0].platform(Arch::Aarch64).is_some());
+        assert!(collection[2].platform(Arch::Aarch64).is_some());
+        assert!(
+            collection[2]
+                .platform(Arch::X86_64)
+                .unwrap()
+                .checksum_link
+                .is_some()
+        );
+        assert!(parse_releases(body, "CL").is_err());
+    }
+
+    #[test]
+    fn test_read_json_keeps_an_arm64_only_release() {
+        let body = r#"{"WS": [
+            {"date": "2025-04-16", "type": "release", "version": "2025.1", "downloads": {
+                "linuxARM64": {"link": "https://download.jetbrains.com/webstorm/WebStorm-2025.1-aarch64.tar.gz", "size": 1098479811}
+            }},
+            {"date": "2025-04-01", "type": "release", "version": "2024.3.6", "downloads": {
+                "mac": {"link": "https://download.jetbrains.com/webstorm/WebStorm-2024.3.6.dmg", "size": 1}
+            }}
+        ]}"#;
+        let records = parse_releases(body, "WS").unwrap();
+        let collection = read_json(&records).unwrap();
+        assert_eq!(collection.len(), 1);
+        assert!(collection[0].platform(Arch::X86_64).is_none());
+        assert_eq!(
+            collection[0].platform(Arch::Aarch64).unwrap().size,
+            1098479811
+        );
+    }
 }
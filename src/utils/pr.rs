@@ -0,0 +1,138 @@
+use crate::resolve::{Arch, Checksum, Platform, ProductRelease};
+use std::fmt::Write;
+
+/// Renders the Markdown body of the pull request which moves the manifest from `old` to
+/// `release`, with a row per arch in `arches` and the `skipped` releases in between.
+///
+/// The checksums of `release` are expected to be completed already.
+pub fn render_pr_body(
+    name: &str,
+    old: Option<&str>,
+    release: &ProductRelease,
+    arches: &[Arch],
+    skipped: &[ProductRelease],
+) -> String {
+    let mut body = String::new();
+    match old {
+        Some(old) => writeln!(
+            body,
+            "## Update {} from {} to {}",
+            name, old, release.version
+        ),
+        None => writeln!(body, "## Update {} to {}", name, release.version),
+    }
+    .unwrap();
+    body.push('\n');
+    writeln!(
+        body,
+        "- Version: {}{}",
+        release.version,
+        release
+            .build
+            .map(|build| format!(" (build {})", build))
+            .unwrap_or_default()
+    )
+    .unwrap();
+    writeln!(body, "- Released: {}", release.date).unwrap();
+    if let Some(notes) = release.notes_link {
+        writeln!(body, "- Release notes: {}", notes).unwrap();
+    }
+
+    body.push_str("\n| Arch | Download | Size | Checksum |\n| --- | --- | --- | --- |\n");
+    for arch in arches {
+        match release.platform(*arch) {
+            Some(platform) => writeln!(
+                body,
+                "| {} | [{}]({}) | {} | {} |",
+                arch,
+                platform.link.rsplit('/').next().unwrap_or(platform.link),
+                platform.link,
+                format_size(platform.size),
+                format_checksum(platform)
+            ),
+            None => writeln!(
+                body,
+                "| {} | no download, the source stays as it is | | |",
+                arch
+            ),
+        }
+        .unwrap();
+    }
+
+    if !skipped.is_empty() {
+        body.push_str("\n### Skipped releases\n\n");
+        for release in skipped {
+            write!(body, "- {} ({})", release.version, release.date).unwrap();
+            if let Some(notes) = release.notes_link {
+                write!(body, ", [release notes]({})", notes).unwrap();
+            }
+            body.push('\n');
+        }
+    }
+    body
+}
+
+#[inline]
+fn format_size(size: usize) -> String {
+    format!(
+        "{} bytes ({:.1} MiB)",
+        size,
+        size as f64 / (1024.0 * 1024.0)
+    )
+}
+
+#[inline]
+fn format_checksum(platform: &Platform) -> String {
+    match &platform.checksum_link {
+        Some(Checksum::TypeAndRes(algorithm, hash)) => format!("{} `{}`", algorithm, hash),
+        Some(Checksum::Link(link)) => format!("[not fetched]({})", link),
+        None => "unavailable".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn release<'a>(version: &'a str, date: &'a str) -> ProductRelease<'a> {
+        let mut release = ProductRelease::stub(version, date);
+        release.set_platform(
+            Arch::X86_64,
+            Some(Platform::stub(
+                "https://download.jetbrains.com/webstorm/WebStorm-2025.1.tar.gz",
+                1048576,
+                "f181eb13",
+            )),
+        );
+        release
+    }
+
+    #[test]
+    fn test_render_pr_body() {
+        let mut new = release("2025.1", "2025-04-16");
+        new.build = Some("251.23774.424");
+        new.notes_link = Some("https://youtrack.jetbrains.com/articles/WEB-A-231736001");
+        let mut skipped = release("2024.3.6", "2025-04-01");
+        skipped.notes_link = Some("https://example.com/notes");
+
+        let body = render_pr_body("WebStorm", Some("2024.3.5"), &new, &Arch::ALL, &[skipped]);
+        assert_eq!(
+            body,
+            "## Update WebStorm from 2024.3.5 to 2025.1\n\n\
+             - Version: 2025.1 (build 251.23774.424)\n\
+             - Released: 2025-04-16\n\
+             - Release notes: https://youtrack.jetbrains.com/articles/WEB-A-231736001\n\n\
+             | Arch | Download | Size | Checksum |\n\
+             | --- | --- | --- | --- |\n\
+             | x86_64 | [WebStorm-2025.1.tar.gz](https://download.jetbrains.com/webstorm/WebStorm-2025.1.tar.gz) \
+             | 1048576 bytes (1.0 MiB) | sha256 `f181eb13` |\n\
+             | aarch64 | no download, the source stays as it is | | |\n\n\
+             ### Skipped releases\n\n\
+             - 2024.3.6 (2025-04-01), [release notes](https://example.com/notes)\n"
+        );
+        assert!(
+            render_pr_body("WebStorm", None, &new, &[], &[])
+                .starts_with("## Update WebStorm to 2025.1\n")
+        );
+    }
+}
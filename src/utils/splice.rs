@@ -0,0 +1,346 @@
+use crate::error;
+use saphyr_parser::{Event, Parser, ScalarStyle};
+use serde_yaml::Value;
+use snafu::{OptionExt, whatever};
+use std::collections::HashMap;
+use std::ops::Range;
+
+/// One step of the path from the document root to a node.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+/// Where a scalar lives in the source text, `range` is in bytes and includes the quotes.
+#[derive(Debug)]
+struct ScalarSpan {
+    range: Range<usize>,
+    style: ScalarStyle,
+}
+
+enum Frame {
+    Mapping { key: Option<String> },
+    Sequence { next: usize },
+}
+
+/// A difference which can be written without touching the surrounding text.
+enum Edit {
+    /// The scalar at the path takes a new value.
+    Value(Value),
+    /// The key of the entry at the path is renamed, its value is handled separately.
+    Key(String),
+}
+
+#[derive(Default)]
+struct Spans {
+    values: HashMap<Vec<Segment>, ScalarSpan>,
+    /// Keyed by the path of the entry, i.e. the same path as its value.
+    keys: HashMap<Vec<Segment>, ScalarSpan>,
+}
+
+/// Records the span of every scalar value and mapping key in `text`, keyed by its path.
+///
+/// Works for both YAML and JSON sources, since the latter is parsed as YAML flow style.
+fn locate_scalars(text: &str, path: &str) -> error::Result<Spans> {
+    // The parser reports positions in chars, the splice needs bytes
+    let offsets = text
+        .char_indices()
+        .map(|(offset, _)| offset)
+        .chain(std::iter::once(text.len()))
+        .collect::<Vec<usize>>();
+
+    let mut spans = Spans::default();
+    let mut frames: Vec<Frame> = Vec::new();
+    let mut current: Vec<Segment> = Vec::new();
+
+    // Returns the segment under which the next node is placed, or `None` if it is a mapping key
+    let next_segment = |frames: &mut Vec<Frame>| -> Option<Option<Segment>> {
+        match frames.last_mut() {
+            Some(Frame::Mapping { key }) => key.take().map(|key| Some(Segment::Key(key))),
+            Some(Frame::Sequence { next }) => {
+                *next += 1;
+                Some(Some(Segment::Index(*next - 1)))
+            }
+            None => Some(None),
+        }
+    };
+
+    for event in Parser::new_from_str(text) {
+        let (event, span) = match event {
+            Ok(event) => event,
+            Err(e) => whatever!("Failed to scan {}, source: {}", path, e),
+        };
+        match event {
+            Event::MappingStart(..) | Event::SequenceStart(..) => {
+                let segment = next_segment(&mut frames).with_whatever_context(|| {
+                    format!("Complex mapping keys are not supported, path: {}", path)
+                })?;
+                current.extend(segment);
+                frames.push(if matches!(event, Event::MappingStart(..)) {
+                    Frame::Mapping { key: None }
+                } else {
+                    Frame::Sequence { next: 0 }
+                });
+            }
+            Event::MappingEnd | Event::SequenceEnd => {
+                frames.pop();
+                if !frames.is_empty() {
+                    current.pop();
+                }
+            }
+            Event::Scalar(value, style, ..) => {
+                let start = offsets[span.start.index()];
+                let end = offsets[span.end.index()];
+                let range = start..scalar_end(text, start, end, style, path)?;
+                let mut scalar_path = current.clone();
+                match next_segment(&mut frames) {
+                    None => {
+                        if let Some(Frame::Mapping { key }) = frames.last_mut() {
+                            scalar_path.push(Segment::Key(value.to_string()));
+                            spans.keys.insert(scalar_path, ScalarSpan { range, style });
+                            *key = Some(value.into_owned());
+                        }
+                    }
+                    Some(segment) => {
+                        scalar_path.extend(segment);
+                        spans
+                            .values
+                            .insert(scalar_path, ScalarSpan { range, style });
+                    }
+                }
+            }
+            Event::Alias(_) => {
+                next_segment(&mut frames);
+            }
+            _ => {}
+        }
+    }
+    Ok(spans)
+}
+
+/// The parser is not precise about where quoted scalars end, so find the closing quote by hand.
+fn scalar_end(
+    text: &str,
+    start: usize,
+    end: usize,
+    style: ScalarStyle,
+    path: &str,
+) -> error::Result<usize> {
+    let bytes = text.as_bytes();
+    match style {
+        ScalarStyle::Plain => Ok(start + text[start..end].trim_end().len()),
+        ScalarStyle::SingleQuoted => {
+            let mut i = start + 1;
+            while i < bytes.len() {
+                if bytes[i] == b'\'' {
+                    if bytes.get(i + 1) == Some(&b'\'') {
+                        i += 2;
+                        continue;
+                    }
+                    return Ok(i + 1);
+                }
+                i += 1;
+            }
+            whatever!("Unterminated single quoted scalar, path: {}", path)
+        }
+        ScalarStyle::DoubleQuoted => {
+            let mut i = start + 1;
+            while i < bytes.len() {
+                match bytes[i] {
+                    b'\\' => i += 2,
+                    b'"' => return Ok(i + 1),
+                    _ => i += 1,
+                }
+            }
+            whatever!("Unterminated double quoted scalar, path: {}", path)
+        }
+        _ => whatever!("Block scalars can not be edited in place, path: {}", path),
+    }
+}
+
+/// Walks `original` and `updated` side by side and collects the scalars that differ.
+///
+/// A key is only treated as renamed when it sits at the same position of the mapping
+/// and the original key no longer exists, otherwise keys are matched by name.
+fn changed_scalars(
+    original: &Value,
+    updated: &Value,
+    current: &mut Vec<Segment>,
+    changes: &mut Vec<(Vec<Segment>, Edit)>,
+    path: &str,
+) -> error::Result<()> {
+    match (original, updated) {
+        (Value::Mapping(original), Value::Mapping(updated)) => {
+            if original.len() != updated.len() {
+                whatever!(
+                    "Keys were added or removed, which can not be written in place, path: {}",
+                    path
+                );
+            }
+            for (i, (key, value)) in original.iter().enumerate() {
+                let key_str = key.as_str().with_whatever_context(|| {
+                    format!("Non-string mapping keys are not supported, path: {}", path)
+                })?;
+                current.push(Segment::Key(key_str.to_string()));
+                let new_value = match updated.get(key) {
+                    Some(new_value) => new_value,
+                    None => {
+                        let (new_key, new_value) = updated
+                            .iter()
+                            .nth(i)
+                            .filter(|(new_key, _)| !original.contains_key(*new_key))
+                            .and_then(|(new_key, new_value)| Some((new_key.as_str()?, new_value)))
+                            .with_whatever_context(|| {
+                                format!(
+                                    "Key {} was removed, which can not be written in place, path: {}",
+                                    key_str, path
+                                )
+                            })?;
+                        changes.push((current.clone(), Edit::Key(new_key.to_string())));
+                        new_value
+                    }
+                };
+                changed_scalars(value, new_value, current, changes, path)?;
+                current.pop();
+            }
+        }
+        (Value::Sequence(original), Value::Sequence(updated)) => {
+            if original.len() != updated.len() {
+                whatever!(
+                    "Items were added or removed, which can not be written in place, path: {}",
+                    path
+                );
+            }
+            for (i, (value, new_value)) in original.iter().zip(updated.iter()).enumerate() {
+                current.push(Segment::Index(i));
+                changed_scalars(value, new_value, current, changes, path)?;
+                current.pop();
+            }
+        }
+        (Value::Tagged(original), Value::Tagged(updated)) => {
+            changed_scalars(&original.value, &updated.value, current, changes, path)?;
+        }
+        (original, updated) if original == updated => {}
+        (
+            Value::Null | Value::Bool(_) | Value::Number(_) | Value::String(_),
+            Value::Null | Value::Bool(_) | Value::Number(_) | Value::String(_),
+        ) => changes.push((current.clone(), Edit::Value(updated.clone()))),
+        _ => whatever!(
+            "A node changed its kind, which can not be written in place, path: {}",
+            path
+        ),
+    }
+    Ok(())
+}
+
+/// Renders `value` in the same quoting style as the scalar it replaces.
+fn render_scalar(value: &Value, style: ScalarStyle, path: &str) -> error::Result<String> {
+    let text = match value {
+        Value::Null => "null".to_string(),
+        Value::Bool(b) => b.to_string(),
+        Value::Number(n) => n.to_string(),
+        Value::String(s) => s.clone(),
+        _ => whatever!("Only scalars can be written in place, path: {}", path),
+    };
+    Ok(match (style, value) {
+        (ScalarStyle::SingleQuoted, _) => format!("'{}'", text.replace('\'', "''")),
+        (ScalarStyle::DoubleQuoted, _) => serde_json::to_string(&text)
+            .ok()
+            .whatever_context("Failed to quote scalar, this is a bug")?,
+        (_, Value::String(_)) => {
+            // Let the serializer decide whether the string is safe to be written plain
+            let rendered = serde_yaml::to_string(value)
+                .ok()
+                .whatever_context("Failed to serialize scalar, this is a bug")?;
+            let rendered = rendered.trim_end_matches('\n');
+            if rendered.contains('\n') {
+                whatever!(
+                    "Multi-line scalars can not be written in place, path: {}",
+                    path
+                );
+            }
+            rendered.to_string()
+        }
+        _ => text,
+    })
+}
+
+/// Applies the scalar differences between `original` and `updated` onto `text`,
+/// leaving every other byte untouched.
+pub fn splice_scalars(
+    text: &str,
+    original: &Value,
+    updated: &Value,
+    path: &str,
+) -> error::Result<String> {
+    let mut changes = Vec::new();
+    changed_scalars(original, updated, &mut Vec::new(), &mut changes, path)?;
+    if changes.is_empty() {
+        return Ok(text.to_string());
+    }
+
+    let spans = locate_scalars(text, path)?;
+    let mut edits = changes
+        .into_iter()
+        .map(|(segments, edit)| {
+            let (spans, value) = match edit {
+                Edit::Value(value) => (&spans.values, value),
+                Edit::Key(key) => (&spans.keys, Value::String(key)),
+            };
+            let span = spans.get(&segments).with_whatever_context(|| {
+                format!(
+                    "Failed to locate {:?} in source text, path: {}",
+                    segments, path
+                )
+            })?;
+            Ok((span.range.clone(), render_scalar(&value, span.style, path)?))
+        })
+        .collect::<error::Result<Vec<(Range<usize>, String)>>>()?;
+
+    // Splice from the back so that earlier ranges stay valid
+    edits.sort_unstable_by_key(|(range, _)| std::cmp::Reverse(range.start));
+    let mut output = text.to_string();
+    for (range, replacement) in edits {
+        output.replace_range(range, &replacement);
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_splice_keeps_comments_and_quoting() {
+        let text = "# leading comment\nsources:\n  - url: 'https://a/1.tar.gz'   # pinned\n    size: 1\n    sha256: \"aa\"\n  - {url: b, size: 2}\n";
+        let original: Value = serde_yaml::from_str(text).unwrap();
+        let mut updated = original.clone();
+        updated["sources"][0]["url"] = Value::from("https://a/2.tar.gz");
+        updated["sources"][0]["size"] = Value::from(10);
+        updated["sources"][0]["sha256"] = Value::from("bb");
+        updated["sources"][1]["url"] = Value::from("c");
+        let output = splice_scalars(text, &original, &updated, "test").unwrap();
+        assert_eq!(
+            output,
+            "# leading comment\nsources:\n  - url: 'https://a/2.tar.gz'   # pinned\n    size: 10\n    sha256: \"bb\"\n  - {url: c, size: 2}\n"
+        );
+    }
+
+    #[test]
+    fn test_splice_renames_keys_in_place() {
+        let text = "- url: a\n  sha256: aa # hash\n  size: 1\n";
+        let original: Value = serde_yaml::from_str(text).unwrap();
+        let mut updated = original.clone();
+        let source = updated[0].as_mapping_mut().unwrap();
+        *source = source
+            .iter()
+            .map(|(key, value)| match key.as_str() {
+                Some("sha256") => (Value::from("sha512"), Value::from("bb")),
+                _ => (key.clone(), value.clone()),
+            })
+            .collect();
+        let output = splice_scalars(text, &original, &updated, "test").unwrap();
+        assert_eq!(output, "- url: a\n  sha512: bb # hash\n  size: 1\n");
+    }
+}
@@ -0,0 +1,184 @@
+use crate::error;
+use crate::http::Http;
+use crate::resolve::{Arch, Checksum, HashAlgorithm, Platform, ProductRelease};
+use sha2::{Digest, Sha256, Sha512};
+use snafu::{ResultExt, whatever};
+use std::io::Write;
+
+enum Hasher {
+    Sha256(Sha256),
+    Sha512(Sha512),
+}
+
+impl Hasher {
+    fn new(algorithm: HashAlgorithm) -> Hasher {
+        match algorithm {
+            HashAlgorithm::Sha256 => Hasher::Sha256(Sha256::new()),
+            HashAlgorithm::Sha512 => Hasher::Sha512(Sha512::new()),
+        }
+    }
+
+    fn update(&mut self, chunk: &[u8]) {
+        match self {
+            Hasher::Sha256(hasher) => hasher.update(chunk),
+            Hasher::Sha512(hasher) => hasher.update(chunk),
+        }
+    }
+
+    fn finalize(self) -> String {
+        match self {
+            Hasher::Sha256(hasher) => format!("{:x}", hasher.finalize()),
+            Hasher::Sha512(hasher) => format!("{:x}", hasher.finalize()),
+        }
+    }
+}
+
+/// Hashes a download chunk by chunk and reports the progress on stderr.
+struct Verifier<'a> {
+    name: &'a str,
+    expected_size: usize,
+    received: usize,
+    reported: usize,
+    algorithm: HashAlgorithm,
+    hasher: Hasher,
+}
+
+impl<'a> Verifier<'a> {
+    fn new(name: &'a str, expected_size: usize, algorithm: HashAlgorithm) -> Verifier<'a> {
+        Verifier {
+            name,
+            expected_size,
+            received: 0,
+            reported: usize::MAX,
+            algorithm,
+            hasher: Hasher::new(algorithm),
+        }
+    }
+
+    fn update(&mut self, chunk: &[u8]) {
+        self.hasher.update(chunk);
+        self.received += chunk.len();
+        let percent = self.received * 100 / self.expected_size.max(1);
+        if percent != self.reported {
+            self.reported = percent;
+            eprint!(
+                "\rVerifying {}: {}% ({} / {} MiB)",
+                self.name,
+                percent,
+                self.received >> 20,
+                self.expected_size >> 20
+            );
+            let _ = std::io::stderr().flush();
+        }
+    }
+
+    fn finish(self, expected: &str) -> error::Result<()> {
+        eprintln!();
+        if self.received != self.expected_size {
+            whatever!(
+                "Size mismatch for {}: expected {} bytes, downloaded {}",
+                self.name,
+                self.expected_size,
+                self.received
+            );
+        }
+        let actual = self.hasher.finalize();
+        if !actual.eq_ignore_ascii_case(expected) {
+            whatever!(
+                "Checksum mismatch for {}: expected {} {}, downloaded {}",
+                self.name,
+                self.algorithm,
+                expected,
+                actual
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Streams the tarball of `platform` and checks it against its size and resolved checksum.
+async fn verify_platform(http: &Http, platform: &Platform<'_>) -> error::Result<()> {
+    let (algorithm, expected) = match &platform.checksum_link {
+        Some(Checksum::TypeAndRes(algorithm, res)) => (*algorithm, res),
+        _ => whatever!("The checksum of {} has not been resolved", platform.link),
+    };
+    let name = platform.link.rsplit('/').next().unwrap_or(platform.link);
+
+    let mut response = http.get(platform.link).await?;
+    let mut verifier = Verifier::new(name, platform.size, algorithm);
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .with_whatever_context(|e| format!("Failed to download {}, source: {}", platform.link, e))?
+    {
+        verifier.update(&chunk);
+    }
+    verifier.finish(expected)
+}
+
+/// Downloads the Linux tarballs of `release` for `arches`, the arches of the manifest, and
+/// refuses it unless all of them match. An arch the release lacks is left to `render_yaml`.
+pub async fn verify_release(
+    http: &Http,
+    release: &ProductRelease<'_>,
+    arches: &[Arch],
+) -> error::Result<()> {
+    for arch in arches {
+        if let Some(platform) = release.platform(*arch) {
+            verify_platform(http, platform).await?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::mock::MockServer;
+
+    #[test]
+    fn test_verifier() {
+        // sha256 of "hello world"
+        let sha256 = "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9";
+        let verify = |chunks: &[&[u8]], size: usize| {
+            let mut verifier = Verifier::new("test", size, HashAlgorithm::Sha256);
+            chunks.iter().for_each(|chunk| verifier.update(chunk));
+            verifier.finish(sha256)
+        };
+        assert!(verify(&[b"hello ", b"world"], 11).is_ok());
+        assert!(verify(&[b"hello ", b"world"], 12).is_err());
+        assert!(verify(&[b"hello ", b"there"], 11).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_verify_release_only_downloads_the_manifest_arches() {
+        let server =
+            MockServer::start(&[("/webstorm/WebStorm-2025.1.tar.gz", &[(200, "hello world")])])
+                .await;
+        let http = server.http();
+        let sha256 = "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9";
+        let mut release = ProductRelease::stub("2025.1", "2025-04-16");
+        release.set_platform(
+            Arch::X86_64,
+            Some(Platform::stub(
+                "https://download.jetbrains.com/webstorm/WebStorm-2025.1.tar.gz",
+                11,
+                sha256,
+            )),
+        );
+        release.set_platform(
+            Arch::Aarch64,
+            Some(Platform::stub(
+                "https://download.jetbrains.com/webstorm/WebStorm-2025.1-aarch64.tar.gz",
+                11,
+                sha256,
+            )),
+        );
+
+        // The aarch64 tarball is not served, so requesting it would fail
+        verify_release(&http, &release, &[Arch::X86_64])
+            .await
+            .unwrap();
+        assert!(verify_release(&http, &release, &Arch::ALL).await.is_err());
+    }
+}
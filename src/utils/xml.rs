@@ -2,13 +2,15 @@
 use crate::error;
 // 引入项目内 ProductRelease 结构体定义
 use crate::resolve::ProductRelease;
+// 引入项目内文件变更结构体，用于在内存中保存渲染结果
+use crate::utils::FileChange;
 // 引入项目内 XML 工具库中的错误消息生成函数
 use crate::utils::xml::_err::failed_to_write_event;
 
 // 从 quick_xml 库引入处理 XML 属性所需的相关类型
 use quick_xml::events::attributes::{Attribute, Attributes};
 // 从 quick_xml 库引入 XML 事件类型，如开始标签、结束标签等
-use quick_xml::events::{BytesEnd, Event};
+use quick_xml::events::{BytesEnd, BytesText, Event};
 // 从 quick_xml 库引入 XML 读取器和写入器
 use quick_xml::{Reader, Writer};
 
@@ -19,6 +21,8 @@ use snafu::{OptionExt, ResultExt, whatever}; // whatever! 用于创建临时的
 use std::borrow::Cow;
 // 从标准库引入 Cursor，用于在内存中的字节向量上实现 Read 和 Write Trait
 use std::io::Cursor;
+// 从标准库引入 Path，用于表示 XML 文件路径
+use std::path::Path;
 
 // 定义一个内部模块，用于存放错误处理相关的辅助函数
 mod _err {
@@ -60,11 +64,18 @@ trait XMLWriter {
 impl XMLWriter for ProductRelease<'_> {
     /// 将 `ProductRelease` 写入为一个空的 `<release>` XML 标签。
     /// 例如：`<release version="1.0.0" date="2023-01-01"/>`
+    /// 非稳定版本（EAP、RC）会按 AppStream 规范额外标记 `type="development"`。
     fn write_xml(&self, writer: &mut Writer<Cursor<Vec<u8>>>) -> error::Result<()> {
-        writer
+        let element = writer
             .create_element("release") // 创建名为 "release" 的元素
             .with_attribute(Attribute::from(("version", self.version))) // 添加 "version" 属性
-            .with_attribute(Attribute::from(("date", self.date))) // 添加 "date" 属性
+            .with_attribute(Attribute::from(("date", self.date))); // 添加 "date" 属性
+        let element = if self.release_type.is_stable() {
+            element
+        } else {
+            element.with_attribute(Attribute::from(("type", "development"))) // 标记为开发版本
+        };
+        element
             .write_empty() // 写入为一个空标签（自闭合标签）
             .with_whatever_context(failed_to_write_event)?; // 如果写入失败，使用 `failed_to_write_event` 生成错误信息
         Ok(())
@@ -73,7 +84,7 @@ impl XMLWriter for ProductRelease<'_> {
     /// 返回 `ProductRelease` 中的日期字符串引用。
     #[inline]
     fn date(&self) -> &str {
-        &self.date
+        self.date
     }
 }
 
@@ -113,6 +124,8 @@ struct XMLHandler<'a, 'b, 'c> {
     reader: Reader<&'a [u8]>, // 用于读取原始 XML 内容的读取器
     vec: &'c mut Vec<ProductRelease<'b>>, // 外部传入的、包含新发布信息的可变 Vec 的引用
     preserved_xml_fragments: Vec<(String, Vec<Event<'a>>)>, // 用于存储需要从原 XML 保留下来的发布片段（日期和对应的事件序列）
+    indent: Option<BytesText<'a>>, // 第一个 <release> 之前的空白，作为兄弟节点之间的缩进
+    closing: Option<BytesText<'a>>, // </releases> 之前的空白，保持结束标签原有的缩进
 }
 
 /// 宏，用于简化处理 XML 读取事件时产生的错误。
@@ -146,11 +159,8 @@ impl<'a, 'b, 'c> XMLHandler<'a, 'b, 'c> {
     ///
     /// * `error::Result<()>` - 成功或失败。
     fn handle_end_of_releases(&mut self, e: BytesEnd) -> error::Result<()> {
-        // 注意：写入 BOM (Byte Order Mark) 的代码被注释掉了。
-        // 如果需要确保输出文件有 UTF-8 BOM，可以取消注释下面这几行。
-        // self.writer
-        //     .write_bom()
-        //     .with_whatever_context(failed_to_write_event)?;
+        // 注意：这里不能写入 BOM (Byte Order Mark)，BOM 只允许出现在文档开头，
+        // 原文件开头若有 BOM，会随 <releases> 之前的事件原样写回。
 
         // 从 `self.vec` (新的发布信息) 中移除那些日期已经存在于 `preserved_xml_fragments` (保留的旧发布片段) 中的条目。
         // 这意味着如果新旧发布信息有相同的日期，旧的会被保留，新的会被丢弃。
@@ -168,16 +178,40 @@ impl<'a, 'b, 'c> XMLHandler<'a, 'b, 'c> {
                 .chain(self.preserved_xml_fragments.iter().map(|e| e as &dyn XMLWriter)), // 链接上保留的旧片段的迭代器，并将 &(String, Vec<Event>) 转换为 &dyn XMLWriter
         );
 
-        // 使用不稳定排序（可能更快，但不保证相等元素的相对顺序）按日期对合并后的列表进行排序。
-        // `sort_unstable_by_key` 使用 `XMLWriter` trait 的 `date()` 方法获取排序的键（日期字符串）。
-        vec_new.sort_unstable_by_key(|e| e.date());
+        // 使用稳定排序按日期从新到旧排列合并后的列表。
+        // 日期相同的条目保持原有的相对顺序（新发布信息在前，保留片段按原文件顺序），
+        // 避免每次运行都产生无意义的顺序变化。
+        vec_new.sort_by(|a, b| b.date().cmp(a.date()));
 
-        // 逆序遍历排序后的列表，并将每个元素写入到 XML Writer 中。
-        // 逆序写入可能是为了让最新的发布信息出现在 XML 文件的最前面（这取决于日期的格式和排序）。
-        for xw in vec_new.iter().rev() {
+        // 兄弟节点之间的缩进：优先使用原文件中第一个 <release> 之前的空白，
+        // 若原文件中没有任何 <release>，则在 </releases> 的缩进基础上多缩进两个空格。
+        let indent = match (&self.indent, &self.closing) {
+            (Some(indent), _) => indent.clone(),
+            (None, Some(closing)) => {
+                let mut indent = closing.to_vec();
+                indent.extend_from_slice(b"  ");
+                BytesText::from_escaped(String::from_utf8(indent).whatever_context(
+                    "Failed to convert indent to UTF-8, this is a bug, please report it and post logs.",
+                )?)
+            }
+            (None, None) => BytesText::from_escaped("\n    "),
+        };
+
+        // 依次写入每个元素，每个元素之前写入与兄弟节点一致的缩进。
+        for xw in vec_new.iter() {
+            self.writer
+                .write_event(Event::Text(indent.clone()))
+                .with_whatever_context(failed_to_write_event)?;
             xw.write_xml(&mut self.writer)?; // 调用每个元素的 write_xml 方法
         }
 
+        // 写回 </releases> 之前原有的空白，保持结束标签的缩进。
+        if let Some(closing) = self.closing.take() {
+            self.writer
+                .write_event(Event::Text(closing))
+                .with_whatever_context(failed_to_write_event)?;
+        }
+
         // 最后，写入 `</releases>` 结束标签。
         self.writer
             .write_event(Event::End(e)) // 写入结束事件
@@ -206,7 +240,7 @@ impl<'a, 'b, 'c> XMLHandler<'a, 'b, 'c> {
         ///     - `Cow<'a, [u8]>`: 日期属性的值 (可能是借用的，也可能是拥有的)。
         fn search_date<'a>(
             mut attr: Attributes<'a>, // 接收属性迭代器
-            vec: &Vec<ProductRelease>, // 接收新发布信息的引用
+            vec: &[ProductRelease], // 接收新发布信息的引用
         ) -> error::Result<(bool, Cow<'a, [u8]>)> {
             // 查找名为 "date" 的属性
             let value = attr
@@ -229,12 +263,18 @@ impl<'a, 'b, 'c> XMLHandler<'a, 'b, 'c> {
 
         // 标志位，指示当前是否正在处理一个应该被跳过（即不保留）的 <release>...</release> 块
         let mut is_skip_release = false;
+        // 标志位，指示当前是否位于某个 <release>...</release> 块内部
+        let mut in_release = false;
+        // 位于 <release> 块之间、尚未确定归属的空白文本
+        let mut pending_whitespace: Option<BytesText<'a>> = None;
 
         // 循环处理 <releases> 标签内部的事件
         loop {
             match self.reader.read_event() {
                 // 匹配到 </releases> 结束标签
                 Ok(Event::End(e)) if e.name().as_ref() == b"releases" => {
+                    // </releases> 之前的空白决定结束标签的缩进
+                    self.closing = pending_whitespace.take();
                     // 调用处理结束标签的函数，并返回其结果
                     return self.handle_end_of_releases(e);
                 }
@@ -249,6 +289,12 @@ impl<'a, 'b, 'c> XMLHandler<'a, 'b, 'c> {
                 // 匹配到 <release ...> 开始标签
                 Ok(Event::Start(e)) if e.name().as_ref() == b"release" => {
                     is_skip_release = false; // 重置跳过标志
+                    in_release = true;
+                    // 记录第一个 <release> 之前的空白，作为兄弟节点的缩进
+                    let whitespace = pending_whitespace.take();
+                    if self.indent.is_none() {
+                        self.indent = whitespace;
+                    }
                     // 搜索日期属性，并检查是否存在于新发布列表中
                     let (is_exist, value) = search_date(e.attributes(), self.vec)?;
                     if is_exist {
@@ -268,6 +314,10 @@ impl<'a, 'b, 'c> XMLHandler<'a, 'b, 'c> {
                 // 匹配到 <release ... /> 空标签（自闭合标签）
                 Ok(Event::Empty(e)) if e.name().as_ref() == b"release" => {
                     // 逻辑与 Event::Start 类似
+                    let whitespace = pending_whitespace.take();
+                    if self.indent.is_none() {
+                        self.indent = whitespace;
+                    }
                     let (is_exist, value) = search_date(e.attributes(), self.vec)?;
                     if is_exist {
                         // 如果日期存在，保留这个空标签事件
@@ -283,6 +333,7 @@ impl<'a, 'b, 'c> XMLHandler<'a, 'b, 'c> {
                 }
                 // 匹配到 </release> 结束标签
                 Ok(Event::End(e)) if e.name().as_ref() == b"release" => {
+                    in_release = false;
                     if !is_skip_release {
                         // 如果没有设置跳过标志 (即这个 release 块是被保留的)
                         // 获取最后添加的那个保留片段 (应该是刚刚处理的 <release> 对应的片段)
@@ -297,6 +348,11 @@ impl<'a, 'b, 'c> XMLHandler<'a, 'b, 'c> {
                         is_skip_release = false;
                     }
                 }
+                // 匹配到 <release> 块之间仅包含空白的文本（换行与缩进）
+                // 这些空白不归属于任何片段，写回时统一使用记录下来的缩进
+                Ok(Event::Text(e)) if !in_release && e.iter().all(u8::is_ascii_whitespace) => {
+                    pending_whitespace = Some(e);
+                }
                 // 匹配到其他任何 XML 事件 (如文本、注释、CDATA 等)
                 Ok(e) => {
                     if !is_skip_release {
@@ -315,7 +371,7 @@ impl<'a, 'b, 'c> XMLHandler<'a, 'b, 'c> {
                 // 处理读取事件时发生的错误
                 Err(e) => {
                     // 使用预定义的宏来创建并返回错误
-                    return handle_read_event_err!(self, e);
+                    handle_read_event_err!(self, e);
                 }
             }
         }
@@ -356,41 +412,40 @@ impl<'a, 'b, 'c> XMLHandler<'a, 'b, 'c> {
                 // 处理读取事件时发生的错误
                 Err(e) => {
                     // 使用宏创建并返回错误
-                    return handle_read_event_err!(self, e);
+                    handle_read_event_err!(self, e);
                 }
             }
         }
     }
 }
 
-/// 公开函数，用于更新指定路径的 XML 文件。
+/// 在内存中渲染更新后的 XML 内容。
 ///
 /// # Arguments
 ///
-/// * `path` - 要更新的 XML 文件的路径。
+/// * `xml` - 原始 XML 内容。
+/// * `path` - XML 文件的路径，仅用于错误报告。
 /// * `vec` - 一个包含新产品发布信息的可变 Vec 的引用。
 ///
 /// # Returns
 ///
-/// * `error::Result<()>` - 成功或失败。
-pub fn update_xml(path: String, vec: &mut Vec<ProductRelease>) -> error::Result<()> {
-    // 读取指定路径的整个 XML 文件内容到字节向量中
-    let xml_bytes = std::fs::read(&path)
-        .with_whatever_context(|x| format!(r#"Failed to read "{}", source: {}"#, path, x))?;
-
-    // 从读取到的字节 slice 创建一个 quick_xml Reader
-    let mut reader = Reader::from_reader(xml_bytes.as_slice());
+/// * `error::Result<String>` - 更新后的 XML 内容。
+fn render(xml: &str, path: &Path, vec: &mut Vec<ProductRelease>) -> error::Result<String> {
+    // 从原始内容的字节 slice 创建一个 quick_xml Reader
+    let mut reader = Reader::from_reader(xml.as_bytes());
     // 配置 Reader：不自动去除文本事件前后的空白字符
     // 这对于保留 XML 的原始格式很重要
     reader.config_mut().trim_text(false);
 
     // 创建 XMLHandler 实例，初始化所有字段
     let mut handler = XMLHandler {
-        path: &path, // 传入文件路径引用
+        path: &path.to_string_lossy(), // 传入文件路径引用
         writer: Writer::new(Cursor::new(Vec::new())), // 创建一个新的 Writer，写入到内存中的 Vec<u8>
         reader, // 传入创建的 Reader
         vec, // 传入新发布信息的可变引用
         preserved_xml_fragments: vec![], // 初始化空的保留片段列表
+        indent: None, // 缩进在读取到第一个 <release> 时确定
+        closing: None, // 结束标签的缩进在读取到 </releases> 时确定
     };
 
     // 调用 handler 的 start 方法开始处理
@@ -398,10 +453,157 @@ pub fn update_xml(path: String, vec: &mut Vec<ProductRelease>) -> error::Result<
 
     // 处理完成后，获取 handler 内部 writer 所写入的全部字节内容
     let output_bytes = handler.writer.into_inner().into_inner();
-    // 将这些字节内容写回到原始文件路径，覆盖原文件
-    std::fs::write(&path, output_bytes)
-        .with_whatever_context(|x| format!(r#"Failed to write "{}", source: {}"#, path, x))?;
+    // 将输出转换为字符串
+    String::from_utf8(output_bytes)
+        .whatever_context("Failed to convert XML output to UTF-8, this is a bug")
+}
+
+/// 公开函数，读取指定路径的 XML 文件并在内存中渲染更新后的内容，不写入磁盘。
+///
+/// # Arguments
+///
+/// * `path` - 要更新的 XML 文件的路径。
+/// * `vec` - 一个包含新产品发布信息的可变 Vec 的引用。
+///
+/// # Returns
+///
+/// * `error::Result<FileChange>` - 原始内容与更新后内容，由调用方决定是否写入。
+pub fn render_xml(path: &Path, vec: &mut Vec<ProductRelease>) -> error::Result<FileChange> {
+    // 读取指定路径的整个 XML 文件内容到字符串中
+    let xml = std::fs::read_to_string(path)
+        .with_whatever_context(|x| format!(r#"Failed to read "{}", source: {}"#, path.display(), x))?;
+    let output = render(&xml, path, vec)?;
+    Ok(FileChange::new(path, xml, output))
+}
+
+/// 读取 appdata 中所有 `<release>` 的 `version` 与 `date` 属性，按文件中的顺序返回，缺少的属性为 `None`。
+fn read_releases(path: &Path) -> error::Result<Vec<(Option<String>, Option<String>)>> {
+    let xml = std::fs::read_to_string(path)
+        .with_whatever_context(|x| format!(r#"Failed to read "{}", source: {}"#, path.display(), x))?;
+    let mut reader = Reader::from_reader(xml.as_bytes());
+    let mut releases = vec![];
+    loop {
+        match reader.read_event() {
+            // <release> 既可能是开始标签，也可能是自闭合标签
+            Ok(Event::Start(e) | Event::Empty(e)) if e.name().as_ref() == b"release" => {
+                // 读取指定属性的值，属性不存在时返回 None
+                let attribute = |key: &str| -> error::Result<Option<String>> {
+                    Ok(e.try_get_attribute(key)
+                        .with_whatever_context(|e| {
+                            format!("Failed to parse release tag's attribute, source: {:?}", e)
+                        })?
+                        .map(|attr| String::from_utf8_lossy(&attr.value).into_owned()))
+                };
+                releases.push((attribute("version")?, attribute("date")?));
+            }
+            Ok(Event::Eof) => return Ok(releases),
+            Ok(_) => {}
+            Err(e) => whatever!(
+                r#"Error happen at position {} while parsing XML in "{}", source: {:?}"#,
+                reader.error_position(),
+                path.display(),
+                e
+            ),
+        }
+    }
+}
 
-    // 一切顺利，返回 Ok
+/// 读取 appdata 中所有 `<release>` 的 `version` 属性，按文件中的顺序返回，
+/// 用于判断 appdata 是否已经包含要写入的版本。
+pub fn release_versions(path: &Path) -> error::Result<Vec<String>> {
+    Ok(read_releases(path)?
+        .into_iter()
+        .filter_map(|(version, _)| version)
+        .collect())
+}
+
+/// 写入后重新解析 appdata，确认第一个 `<release>` 是 `version`，且日期从新到旧排列。
+pub fn verify_appdata(path: &Path, version: &str) -> error::Result<()> {
+    let releases = read_releases(path)?;
+    let first = releases.first().and_then(|(version, _)| version.as_deref());
+    if first != Some(version) {
+        whatever!(
+            r#"The first release in "{}" is {}, expected {}"#,
+            path.display(),
+            first.unwrap_or("missing"),
+            version
+        );
+    }
+    // 日期均为 YYYY-MM-DD，按字符串比较即可
+    for pair in releases.windows(2) {
+        if let [(_, Some(newer)), (older_version, Some(older))] = pair
+            && newer < older
+        {
+            whatever!(
+                r#"The releases in "{}" are not sorted by date, {} of {} follows {}"#,
+                path.display(),
+                older,
+                older_version.as_deref().unwrap_or("unknown"),
+                newer
+            );
+        }
+    }
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resolve::ReleaseType;
+    use regex::Regex;
+
+    const BAK_PATH: &str = "tests/fixtures/com.jetbrains.WebStorm.appdata_bak.xml";
+    const BAK: &str = include_str!("../../tests/fixtures/com.jetbrains.WebStorm.appdata_bak.xml");
+
+    #[test]
+    fn test_render_xml_only_changes_release_lines() {
+        let re = Regex::new(r#"<release version="([^"]+)" date="([^"]+)""#).unwrap();
+        let mut vec = vec![ProductRelease::stub("2025.1", "2025-04-16")];
+        vec.extend(
+            re.captures_iter(BAK).map(|c| {
+                ProductRelease::stub(c.get(1).unwrap().as_str(), c.get(2).unwrap().as_str())
+            }),
+        );
+
+        let output = render(BAK, Path::new(BAK_PATH), &mut vec).unwrap();
+        let expected = BAK.replacen(
+            "  <releases>\n",
+            "  <releases>\n    <release version=\"2025.1\" date=\"2025-04-16\"/>\n",
+            1,
+        );
+        assert_eq!(output, expected);
+        assert!(!output.contains('\u{feff}'));
+    }
+
+    #[test]
+    fn test_release_versions() {
+        let versions = release_versions(Path::new(BAK_PATH)).unwrap();
+        assert_eq!(&versions[..3], ["2024.3.5", "2024.3.1.1", "2024.2.2"]);
+    }
+
+    #[test]
+    fn test_verify_appdata() {
+        let path = Path::new(BAK_PATH);
+        verify_appdata(path, "2024.3.5").unwrap();
+        assert!(verify_appdata(path, "2025.1").is_err());
+
+        let unsorted = std::env::temp_dir().join(format!("fju-unsorted-{}.xml", std::process::id()));
+        let swapped = BAK.replacen(r#"date="2024-09-19""#, r#"date="2025-01-01""#, 1);
+        std::fs::write(&unsorted, swapped).unwrap();
+        assert!(verify_appdata(&unsorted, "2024.3.5").is_err());
+        std::fs::remove_file(unsorted).unwrap();
+    }
+
+    #[test]
+    fn test_render_xml_marks_development_releases() {
+        let xml = "<component>\n  <releases>\n    <release version=\"2025.1\" date=\"2025-04-16\"/>\n  </releases>\n</component>\n";
+        let mut eap = ProductRelease::stub("2025.2 EAP", "2025-05-20");
+        eap.release_type = ReleaseType::Eap;
+        let mut vec = vec![eap, ProductRelease::stub("2025.1", "2025-04-16")];
+        let output = render(xml, Path::new("eap.xml"), &mut vec).unwrap();
+        assert_eq!(
+            output,
+            "<component>\n  <releases>\n    <release version=\"2025.2 EAP\" date=\"2025-05-20\" type=\"development\"/>\n    <release version=\"2025.1\" date=\"2025-04-16\"/>\n  </releases>\n</component>\n"
+        );
+    }
+}
@@ -1,8 +1,12 @@
 use crate::error;
-use crate::resolve::{ProductInfo, ProductRelease};
+use crate::http::Http;
+use crate::resolve::{Arch, Checksum, ProductInfo, ProductRelease};
+use crate::utils::FileChange;
+use crate::utils::splice::splice_scalars;
 use snafu::{OptionExt, ResultExt, whatever};
 
 use serde_yaml::{Mapping, Value};
+use std::path::Path;
 
 trait ValueExt {
     // fn get_map<'a>(&'a self, key: &str, path: &str) -> error::Result<&'a Mapping>;
@@ -58,7 +62,7 @@ impl MappingEx for Mapping {
 
 #[inline]
 fn read_yaml(path: &str) -> error::Result<String> {
-    std::fs::read_to_string(&path)
+    std::fs::read_to_string(path)
         .with_whatever_context(|e| format!("Failed to read yaml file at {}, source: {:?}", path, e))
 }
 
@@ -72,8 +76,28 @@ fn parse_yaml(yaml: String, yaml_path: &str) -> error::Result<Value> {
     })
 }
 
+/// JSON manifests are converted into the YAML tree, so the same matching logic applies.
+#[inline]
+fn parse_json(json: String, json_path: &str) -> error::Result<Value> {
+    let value = serde_json::from_str::<serde_json::Value>(json.as_str()).with_whatever_context(|e| {
+        format!("Failed to parse json file at {}, source: {:?}", json_path, e)
+    })?;
+    serde_yaml::to_value(value).with_whatever_context(|e| {
+        format!("Failed to convert json file at {}, source: {:?}", json_path, e)
+    })
+}
+
+#[inline]
+fn parse_manifest(manifest: String, manifest_path: &str) -> error::Result<Value> {
+    if manifest_path.ends_with(".json") {
+        parse_json(manifest, manifest_path)
+    } else {
+        parse_yaml(manifest, manifest_path)
+    }
+}
+
 fn find_named_map<'a>(
-    modules: &'a mut Vec<Value>,
+    modules: &'a mut [Value],
     product_info: &ProductInfo,
     yaml_path: &str,
 ) -> error::Result<&'a mut Mapping> {
@@ -103,84 +127,89 @@ fn find_named_map<'a>(
         })?
 }
 
+/// The extra-data sources of the product, each serving one arch.
 struct Platforms<'a> {
-    x86_64: &'a mut Mapping,
-    aarch64: Option<&'a mut Mapping>,
+    sources: Vec<(Arch, &'a mut Mapping)>,
 }
 
 impl<'a> Platforms<'a> {
     fn from_collected(
-        collected: &'a mut Vec<&'a mut Mapping>,
+        collected: Vec<&'a mut Mapping>,
         product_info: &ProductInfo,
         yaml_path: &str,
     ) -> error::Result<Platforms<'a>> {
         if collected.is_empty() {
             whatever!(
-                "Cannot find any '{}.tar.gz' file in YAML, path: {}",
-                product_info.lowercase(),
+                "Cannot find any '{}' file in YAML, path: {}",
+                product_info.tarball(),
                 yaml_path
             );
         };
-        if collected.len() == 1 {
-            Ok(Platforms {
-                x86_64: &mut collected[0],
-                aarch64: None,
-            })
-        } else {
-            let mut units = [("x86_64", None), ("aarch64", None)];
-            for i in 0..collected.len() {
-                collected[i].get_mut_err("only-arches", yaml_path).and_then(|v| {
-                    let seq = v.as_sequence().with_whatever_context(|| {
-                        format!("Unexpected YAML structure while reading only-arches, path: {}", yaml_path)
-                    })?;
-                    let seq_str = seq.first().with_whatever_context(|| {
-                        format!("The only-arches sequence contain no values in YAML, path: {}", yaml_path)
-                    })?.as_str().with_whatever_context(|| {
-                        format!("Failed to convert only-arches first element to string in YAML, path: {}",yaml_path)
-                    })?;
-                    for i in 0..units.len() {
-                        if units[i].0.eq(seq_str) {
-                            if units[i].1.is_some() {
-                                whatever!("There are conflict arch software in YAML, path: {}", yaml_path);
-                            }
-                            units[i].1 = Some(i);
-                        }
-                    }
-                    Ok(())
+        // A single source without `only-arches` is taken as the x86_64 one
+        if collected.len() == 1 && !collected[0].contains_key("only-arches") {
+            return Ok(Platforms {
+                sources: collected
+                    .into_iter()
+                    .map(|map| (Arch::X86_64, map))
+                    .collect(),
+            });
+        }
+        let mut sources: Vec<(Arch, &'a mut Mapping)> = Vec::with_capacity(collected.len());
+        for map in collected {
+            let arch = map
+                .get("only-arches")
+                .with_whatever_context(|| {
+                    format!("Failed to find only-arches in yaml, path: {}", yaml_path)
+                })?
+                .as_sequence()
+                .with_whatever_context(|| {
+                    format!(
+                        "Unexpected YAML structure while reading only-arches, path: {}",
+                        yaml_path
+                    )
+                })?
+                .first()
+                .with_whatever_context(|| {
+                    format!(
+                        "The only-arches sequence contain no values in YAML, path: {}",
+                        yaml_path
+                    )
+                })?
+                .as_str()
+                .with_whatever_context(|| {
+                    format!(
+                        "Failed to convert only-arches first element to string in YAML, path: {}",
+                        yaml_path
+                    )
                 })?;
+            // Arches without a JetBrains download are left alone
+            let Some(arch) = Arch::from_flatpak(arch) else {
+                continue;
+            };
+            if sources.iter().any(|(other, _)| *other == arch) {
+                whatever!(
+                    "There are conflict arch software in YAML, path: {}",
+                    yaml_path
+                );
             }
-            if let Some(x86_64_pos) = units[0].1 {
-                return if let Some(aarch64_pos) = units[1].1 {
-                    let [x86_64, aarch64] = collected
-                        .get_disjoint_mut([x86_64_pos, aarch64_pos])
-                        .with_whatever_context(|e| {
-                        format!(
-                            "The pos of different arches is conflict, this is a bug, source: {}",
-                            e
-                        )
-                    })?;
-                    Ok(Platforms {
-                        x86_64,
-                        aarch64: Some(aarch64),
-                    })
-                } else {
-                    let x86_64 = &mut collected[x86_64_pos];
-                    Ok(Platforms {
-                        x86_64,
-                        aarch64: None,
-                    })
-                };
-            }
-            whatever!("Failed to find x86_64 in YAML path: {}", yaml_path)
+            sources.push((arch, map));
         }
+        if sources.is_empty() {
+            whatever!(
+                "Failed to find x86_64 or aarch64 in YAML path: {}",
+                yaml_path
+            )
+        }
+        Ok(Platforms { sources })
     }
 
     fn write_from_release(
         &mut self,
         product_release: &ProductRelease,
+        strict: bool,
         yaml_path: &str,
     ) -> error::Result<()> {
-        use crate::resolve::Platform;
+        use crate::resolve::{HashAlgorithm, Platform};
         let write = |map: &mut Mapping, platform: &Platform| -> error::Result<()> {
             if map.contains_key("size") {
                 *map.get_mut_err("size", yaml_path)? =
@@ -192,24 +221,64 @@ impl<'a> Platforms<'a> {
                 .as_ref()
                 .whatever_context("Checksum has not been requested from the server, this is a bug")?
                 .clone();
-            let (_type, _res) = checksum.into_type_and_res();
-            if !_type.eq("sha256") {
-                whatever!("Different checksum type");
+            let (algorithm, res) = checksum.into_type_and_res();
+            if map.contains_key(algorithm.key()) {
+                *map.get_mut_err(algorithm.key(), yaml_path)? = Value::String(res);
+                return Ok(());
             }
-            *map.get_mut_err("sha256", yaml_path)? = Value::String(_res.clone());
+            // Switch the checksum kind of the source while keeping its position
+            let old_key = [HashAlgorithm::Sha256, HashAlgorithm::Sha512]
+                .iter()
+                .map(|a| a.key())
+                .find(|key| map.contains_key(*key))
+                .with_whatever_context(|| {
+                    format!(
+                        "Failed to find a sha256 or sha512 checksum, path: {}",
+                        yaml_path
+                    )
+                })?;
+            *map = map
+                .iter()
+                .map(|(key, value)| match key.as_str() {
+                    Some(key) if key == old_key => {
+                        (Value::from(algorithm.key()), Value::String(res.clone()))
+                    }
+                    _ => (key.clone(), value.clone()),
+                })
+                .collect();
             Ok(())
         };
 
-        write(&mut self.x86_64, &product_release.linux_amd64)?;
-
-        if let Some(aarch64) = &mut self.aarch64 {
-            write(
-                aarch64,
-                product_release
-                    .linux_arm64
-                    .as_ref()
-                    .whatever_context("Failed to find latest aarch64 in JSON")?,
-            )?;
+        // Every arch is updated on its own, one missing upstream keeps its last good version
+        for (arch, map) in self.sources.iter_mut() {
+            match product_release.platform(*arch) {
+                Some(platform) => write(map, platform)?,
+                None if strict => whatever!(
+                    "{} has no {} download for the {} source, path: {}",
+                    product_release.version,
+                    arch.api_key(),
+                    arch,
+                    yaml_path
+                ),
+                None => eprintln!(
+                    "Warning: {} has no {} download, the {} source stays as it is",
+                    product_release.version,
+                    arch.api_key(),
+                    arch
+                ),
+            }
+        }
+        for arch in Arch::ALL {
+            if product_release.platform(arch).is_some()
+                && !self.sources.iter().any(|(other, _)| *other == arch)
+            {
+                eprintln!(
+                    "Warning: {} has a {} download, but the manifest has no {} source for it",
+                    product_release.version,
+                    arch.api_key(),
+                    arch
+                );
+            }
         }
         Ok(())
     }
@@ -236,7 +305,7 @@ fn collect_platforms<'a>(
                 && KEYS.iter().any(|key| {
                     v.as_mapping().unwrap().contains_key(key)
                         && v.as_mapping().unwrap()[key]
-                            .eq(&format!("{}.tar.gz", product_info.lowercase()))
+                            .eq(product_info.tarball())
                 })
         })
         .collect::<Vec<&mut Value>>();
@@ -253,33 +322,309 @@ fn collect_platforms<'a>(
     })
 }
 
-pub async fn update_yaml(
+/// Product identifiers declared by the manifest itself.
+#[derive(Debug, Default)]
+pub struct ManifestSignals {
+    pub app_id: Option<String>,
+    /// Codes of every `x-checker-data` with `type: jetbrains`, deduplicated
+    pub codes: Vec<String>,
+}
+
+fn collect_checker_codes(value: &Value, codes: &mut Vec<String>) {
+    match value {
+        Value::Mapping(map) => {
+            if let Some(checker) = map.get("x-checker-data")
+                && checker.get("type").and_then(Value::as_str) == Some("jetbrains")
+                && let Some(code) = checker.get("code").and_then(Value::as_str)
+                && !codes.iter().any(|c| c == code)
+            {
+                codes.push(code.to_string());
+            }
+            map.values().for_each(|v| collect_checker_codes(v, codes));
+        }
+        Value::Sequence(seq) => seq.iter().for_each(|v| collect_checker_codes(v, codes)),
+        _ => {}
+    }
+}
+
+/// Reads `app-id` and the jetbrains `x-checker-data` codes from the manifest.
+pub fn read_signals(manifest_path: &Path) -> error::Result<ManifestSignals> {
+    let manifest_path = manifest_path.to_string_lossy();
+
+    let manifest = read_yaml(&manifest_path)?;
+    let root = parse_manifest(manifest, &manifest_path)?;
+    let app_id = ["app-id", "id"]
+        .iter()
+        .find_map(|key| root.get(key).and_then(Value::as_str))
+        .map(str::to_owned);
+    let mut codes = Vec::new();
+    collect_checker_codes(&root, &mut codes);
+    Ok(ManifestSignals { app_id, codes })
+}
+
+/// Resolves the extra-data sources of the product in `root` and hands them to `f`.
+fn with_platforms<T>(
+    root: &mut Value,
+    product_info: &ProductInfo,
+    yaml_path: &str,
+    f: impl FnOnce(Platforms) -> error::Result<T>,
+) -> error::Result<T> {
+    let modules = root.get_seq_mut("modules", yaml_path)?;
+    let named_map = find_named_map(modules, product_info, yaml_path)?;
+    let collected = collect_platforms(named_map, product_info, yaml_path)?;
+    let platforms = Platforms::from_collected(collected, product_info, yaml_path)?;
+    f(platforms)
+}
+
+/// Reads the `url` of every matched extra-data source, keyed by arch.
+pub fn current_links(
+    product_info: &ProductInfo,
+    yaml_path: &Path,
+) -> error::Result<Vec<(Arch, String)>> {
+    let yaml_path = yaml_path.to_string_lossy();
+
+    let yaml = read_yaml(&yaml_path)?;
+    let mut root = parse_manifest(yaml, &yaml_path)?;
+    with_platforms(&mut root, product_info, &yaml_path, |platforms| {
+        let read_url = |map: &mut Mapping| -> error::Result<String> {
+            map.get_mut_err("url", &yaml_path)?
+                .as_str()
+                .map(str::to_owned)
+                .with_whatever_context(|| {
+                    format!("Failed to convert url in YAML, path: {}", yaml_path)
+                })
+        };
+        platforms
+            .sources
+            .into_iter()
+            .map(|(arch, map)| Ok((arch, read_url(map)?)))
+            .collect()
+    })
+}
+
+/// Re-reads the written manifest and checks that every source with a download in `release`
+/// now has its URL, size and checksum.
+pub fn verify_manifest(
     product_info: &ProductInfo,
-    collection: &mut Vec<ProductRelease<'_>>,
+    yaml_path: &Path,
+    release: &ProductRelease,
 ) -> error::Result<()> {
-    let yaml_path = product_info.find_yaml_from_path().whatever_context("Failed to find YAML path")?;
-    
+    let yaml_path = yaml_path.to_string_lossy();
+
     let yaml = read_yaml(&yaml_path)?;
-    let mut root = parse_yaml(yaml, &yaml_path)?;
-    let modules = root.get_seq_mut("modules", &yaml_path)?;
-    let named_map = find_named_map(modules, product_info, &yaml_path)?;
-    let mut collected = collect_platforms(named_map, &product_info, &yaml_path)?;
-    let mut platforms = Platforms::from_collected(&mut collected, &product_info, &yaml_path)?;
-
-    if collection.len() == 0 {
-        println!("It is up to date");
-        return Ok(());
+    let mut root = parse_manifest(yaml, &yaml_path)?;
+    with_platforms(&mut root, product_info, &yaml_path, |platforms| {
+        for (arch, map) in platforms.sources {
+            let Some(platform) = release.platform(arch) else {
+                continue;
+            };
+            let mismatch = |key: &str| -> error::Result<()> {
+                whatever!(
+                    "The {} source has an unexpected {} after writing {}, path: {}",
+                    arch,
+                    key,
+                    release.version,
+                    yaml_path
+                )
+            };
+            if map.get("url").and_then(Value::as_str) != Some(platform.link) {
+                mismatch("url")?;
+            }
+            if map.contains_key("size")
+                && map.get("size").and_then(Value::as_u64) != Some(platform.size as u64)
+            {
+                mismatch("size")?;
+            }
+            if let Some(Checksum::TypeAndRes(algorithm, hash)) = &platform.checksum_link
+                && map.get(algorithm.key()).and_then(Value::as_str) != Some(hash.as_str())
+            {
+                mismatch(algorithm.key())?;
+            }
+        }
+        Ok(())
+    })
+}
+
+/// Writes `release` into the manifest text, only the scalars of the matched sources change.
+fn patch_yaml(
+    yaml: &str,
+    product_info: &ProductInfo,
+    release: &ProductRelease,
+    strict: bool,
+    yaml_path: &str,
+) -> error::Result<String> {
+    let original = parse_manifest(yaml.to_string(), yaml_path)?;
+    let mut root = original.clone();
+    with_platforms(&mut root, product_info, yaml_path, |mut platforms| {
+        platforms.write_from_release(release, strict, yaml_path)
+    })?;
+    splice_scalars(yaml, &original, &root, yaml_path)
+}
+
+/// Renders the manifest with `release` in memory without writing it, `None` keeps it as it is.
+pub async fn render_yaml(
+    http: &Http,
+    product_info: &ProductInfo,
+    yaml_path: &Path,
+    release: Option<&mut ProductRelease<'_>>,
+    strict: bool,
+) -> error::Result<FileChange> {
+    let path = yaml_path;
+    let yaml_path = yaml_path.to_string_lossy();
+
+    let yaml = read_yaml(&yaml_path)?;
+    // Make sure the manifest is usable before requesting anything
+    let mut root = parse_manifest(yaml.clone(), &yaml_path)?;
+    with_platforms(&mut root, product_info, &yaml_path, |_| Ok(()))?;
+
+    let Some(release) = release else {
+        return Ok(FileChange::unchanged(path, yaml));
+    };
+
+    release.complete_checksum(http).await?;
+    let updated = patch_yaml(&yaml, product_info, release, strict, &yaml_path)?;
+
+    Ok(FileChange::new(path, yaml, updated))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::resolve::{HashAlgorithm, Platform};
+    use std::path::PathBuf;
+
+    const BAK: &str = include_str!("../../tests/fixtures/com.jetbrains.WebStorm_bak.yaml");
+    const UPDATED: &str = include_str!("../../tests/fixtures/com.jetbrains.WebStorm.yaml");
+
+    fn webstorm() -> ProductInfo {
+        let config = Config::builtin().unwrap();
+        ProductInfo::from_lowcase_name(
+            PathBuf::from("."),
+            vec!["com.jetbrains.WebStorm.yaml".to_string()],
+            &config.products,
+        )
+        .unwrap()
     }
 
-    let client = reqwest::Client::new();
-    collection[0].complete_checksum(client).await;
-    platforms.write_from_release(&collection[0], &yaml_path)?;
+    fn release_2024_3_5() -> ProductRelease<'static> {
+        let mut release = ProductRelease::stub("2024.3.5", "2025-03-06");
+        release.set_platform(
+            Arch::X86_64,
+            Some(Platform::stub(
+                "https://download.jetbrains.com/webstorm/WebStorm-2024.3.5.tar.gz",
+                1088962559,
+                "da587d7ca3ebb08f067143e4a6b35f1aa133aa10af7fc365496838006fcd1aed",
+            )),
+        );
+        release.set_platform(
+            Arch::Aarch64,
+            Some(Platform::stub(
+                "https://download.jetbrains.com/webstorm/WebStorm-2024.3.5-aarch64.tar.gz",
+                1088287147,
+                "fce5d5c2b8c5aacfabac60ff93b93d7c9a3239adcf8347b3deabd472ac1c1288",
+            )),
+        );
+        release
+    }
 
-    let yaml_str =
-        serde_yaml::to_string(&root).whatever_context("Failed to serialize YAML, this is a bug")?;
-    std::fs::write(&yaml_path, yaml_str).with_whatever_context(|e| {
-        format!("Failed to write YAML to {}, source: {:?}", yaml_path, e)
-    })?;
+    fn release_2025_1() -> ProductRelease<'static> {
+        let mut release = ProductRelease::stub("2025.1", "2025-04-16");
+        release.set_platform(
+            Arch::X86_64,
+            Some(Platform::stub(
+                "https://download.jetbrains.com/webstorm/WebStorm-2025.1.tar.gz",
+                1101448284,
+                "f181eb1348c04a60f860fd9ea9616261c6c012a7916e76380652d129e87503ea",
+            )),
+        );
+        release.set_platform(
+            Arch::Aarch64,
+            Some(Platform::stub(
+                "https://download.jetbrains.com/webstorm/WebStorm-2025.1-aarch64.tar.gz",
+                1098479811,
+                "51fd7420fb49ff8851d3b4b1079f8d7afebe9631a76e7d1d4fd3e66d42332c6c",
+            )),
+        );
+        release
+    }
+
+    #[test]
+    fn test_unchanged_manifest_round_trips() {
+        let output = patch_yaml(BAK, &webstorm(), &release_2024_3_5(), false, "bak").unwrap();
+        assert_eq!(output, BAK);
+        let output = patch_yaml(UPDATED, &webstorm(), &release_2025_1(), false, "updated").unwrap();
+        assert_eq!(output, UPDATED);
+    }
+
+    #[test]
+    fn test_update_only_touches_sources() {
+        let output = patch_yaml(BAK, &webstorm(), &release_2025_1(), false, "bak").unwrap();
+        assert_eq!(output, UPDATED);
+    }
+
+    #[test]
+    fn test_json_manifest_keeps_order_and_indentation() {
+        let to_json = |yaml: &str| {
+            let value = serde_yaml::from_str::<Value>(yaml).unwrap();
+            serde_json::to_string_pretty(&value).unwrap() + "\n"
+        };
+        let (bak, updated) = (to_json(BAK), to_json(UPDATED));
+        let output = patch_yaml(&bak, &webstorm(), &release_2024_3_5(), false, "bak.json").unwrap();
+        assert_eq!(output, bak);
+        let output = patch_yaml(&bak, &webstorm(), &release_2025_1(), false, "bak.json").unwrap();
+        assert_eq!(output, updated);
+    }
+
+    #[test]
+    fn test_sha512_replaces_sha256_in_place() {
+        let sha512 = "ab".repeat(64);
+        let mut release = release_2025_1();
+        release.platform_mut(Arch::X86_64).unwrap().checksum_link =
+            Some(Checksum::TypeAndRes(HashAlgorithm::Sha512, sha512.clone()));
+        let output = patch_yaml(BAK, &webstorm(), &release, false, "bak").unwrap();
+        let expected = UPDATED.replacen(
+            "sha256: f181eb1348c04a60f860fd9ea9616261c6c012a7916e76380652d129e87503ea",
+            &format!("sha512: {}", sha512),
+            1,
+        );
+        assert_ne!(expected, UPDATED);
+        assert_eq!(output, expected);
+    }
 
-    Ok(())
+    #[test]
+    fn test_missing_arch_keeps_its_source() {
+        let mut release = release_2025_1();
+        release.set_platform(Arch::Aarch64, None);
+        let output = patch_yaml(BAK, &webstorm(), &release, false, "bak").unwrap();
+        let (x86_64, aarch64) = output.split_once("only-arches").unwrap();
+        assert!(x86_64.contains("WebStorm-2025.1.tar.gz"));
+        assert!(aarch64.contains("WebStorm-2024.3.5-aarch64.tar.gz"));
+        assert!(
+            aarch64.contains("fce5d5c2b8c5aacfabac60ff93b93d7c9a3239adcf8347b3deabd472ac1c1288")
+        );
+        assert!(patch_yaml(BAK, &webstorm(), &release, true, "bak").is_err());
+
+        let mut release = release_2025_1();
+        release.set_platform(Arch::X86_64, None);
+        let output = patch_yaml(BAK, &webstorm(), &release, false, "bak").unwrap();
+        let (x86_64, aarch64) = output.split_once("only-arches").unwrap();
+        assert!(x86_64.contains("WebStorm-2024.3.5.tar.gz"));
+        assert!(
+            x86_64.contains("da587d7ca3ebb08f067143e4a6b35f1aa133aa10af7fc365496838006fcd1aed")
+        );
+        assert!(aarch64.contains("WebStorm-2025.1-aarch64.tar.gz"));
+        assert!(patch_yaml(BAK, &webstorm(), &release, true, "bak").is_err());
+    }
+
+    #[test]
+    fn test_verify_manifest() {
+        let path = Path::new("tests/fixtures/com.jetbrains.WebStorm.yaml");
+        verify_manifest(&webstorm(), path, &release_2025_1()).unwrap();
+        assert!(verify_manifest(&webstorm(), path, &release_2024_3_5()).is_err());
+
+        let mut release = release_2025_1();
+        release.platform_mut(Arch::Aarch64).unwrap().size += 1;
+        assert!(verify_manifest(&webstorm(), path, &release).is_err());
+    }
 }